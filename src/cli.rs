@@ -1,8 +1,13 @@
-use super::{AUTHOR, Direction, MAXSPEED, MINSPEED};
+use super::{
+    AUTHOR, DEFAULT_SPEED, Direction, Distribution, Speed, SpeedCurve, TrailLength,
+    image::ImageMode,
+    intro::{IntroConfig, Script},
+    keys::{KeyMap, Keys},
+};
 use clap::{Parser, crate_description, crate_name, crate_version};
 use ezemoji::{CharGroup, CharWidth, GroupKind, MultiRange};
 use serde::{Deserialize, Deserializer, Serialize};
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
 
 use std::str::FromStr;
 
@@ -51,8 +56,26 @@ pub struct Config {
     pub head: Option<String>,
     pub direction: Option<Direction>,
     pub speed: Option<String>,
+    pub horizontal_speed: Option<String>,
+    pub speed_curve: Option<SpeedCurve>,
+    pub curve_strength: Option<f32>,
     pub display_group: Option<bool>,
+    pub min_length: Option<String>,
+    pub max_length: Option<String>,
+    pub length_distribution: Option<Distribution>,
+    pub density: Option<f32>,
+    pub fps: Option<u32>,
+    pub sync: Option<bool>,
+    pub redraw_threshold: Option<f32>,
+    pub message_color: Option<String>,
+    pub clock: Option<bool>,
+    pub clock_color: Option<String>,
+    pub column_gap: Option<usize>,
+    pub sparse: Option<f32>,
     pub group: Option<String>,
+    pub keys: Option<std::collections::BTreeMap<String, Keys>>,
+    pub intro: Option<IntroConfig>,
+    #[serde(default)]
     pub custom: std::collections::BTreeMap<String, Group>,
 }
 
@@ -96,7 +119,53 @@ pub enum Grouping {
     Custom(Group),
 }
 
+/// Groups the `g` key cycles through while the rain is running.
+const CYCLE_GROUPS: &[&str] = &[
+    "bin",
+    "classic",
+    "jap",
+    "num",
+    "alphalow",
+    "alphaup",
+    "large-letters",
+    "arrow",
+    "cards",
+    "moon",
+    "earth",
+    "clock",
+    "plants",
+    "shapes",
+    "smile",
+    "emojis",
+];
+
+/// Colors the `c` key cycles through while the rain is running.
+const CYCLE_COLORS: &[&str] = &["green", "red", "blue", "white"];
+
+/// The color after `color` in the list the `c` key cycles through.
+pub fn next_color(color: &str) -> &'static str {
+    let next = CYCLE_COLORS
+        .iter()
+        .position(|c| *c == color)
+        .map_or(0, |i| i + 1);
+    CYCLE_COLORS[next % CYCLE_COLORS.len()]
+}
+
 impl Grouping {
+    /// The group after this one in the list the `g` key cycles through.
+    /// Groups that aren't in the list start over at the beginning.
+    pub fn next(&self) -> Grouping {
+        let name = format!("{:?}", self.name());
+        let next = CYCLE_GROUPS
+            .iter()
+            .position(|group| {
+                Grouping::from_str(group).is_ok_and(|group| format!("{:?}", group.name()) == name)
+            })
+            .map_or(0, |i| i + 1);
+        Grouping::from_str(CYCLE_GROUPS[next % CYCLE_GROUPS.len()])
+            .expect("cycle groups are all built in")
+    }
+
     pub fn name(&self) -> GroupKind {
         match self {
             Grouping::EzEmoji(group) => group.name,
@@ -351,6 +420,175 @@ OPTIONS:
     #RRGGBB
 ";
 
+const HELP_MIN_LENGTH: &str = "Set the shortest length of a rain trail.
+OPTIONS:
+    number of cells,     e.g. 4
+    fraction of screen,  e.g. 0.25
+    percent of screen,   e.g. 25%
+";
+
+const HELP_MAX_LENGTH: &str = "Set the longest length of a rain trail.
+Defaults to the screen size minus 4 cells.
+OPTIONS:
+    number of cells,     e.g. 20
+    fraction of screen,  e.g. 0.75
+    percent of screen,   e.g. 75%
+";
+
+const HELP_LENGTH_DISTRIBUTION: &str = "Set how trail lengths are picked.
+Lengths always stay between min and max length.
+OPTIONS:
+    uniform      - Every length is as likely as the next
+    normal       - Most trails are around the middle of the range
+    exponential  - Mostly short trails with the odd long one
+";
+
+const HELP_DENSITY: &str = "Set the fraction of columns that are raining at once.
+Takes a value between 0 and 1.
+";
+
+const HELP_SPEED: &str = "Set the speed of the Rain in cells per second, from 0.01 to 1000.
+Takes a single speed or a range that each drop picks a speed from.
+Drops can't move more than one cell per frame.
+OPTIONS:
+    20,
+    5..30
+";
+
+const HELP_HORIZONTAL_SPEED: &str = "Set the speed of sideways Rain in cells per second.
+Defaults to --speed. Terminal cells are about twice as tall as they
+are wide so sideways Rain covers less ground at the same speed.
+OPTIONS:
+    40,
+    10..60
+";
+
+const HELP_SPEED_CURVE: &str = "Set how the speed of a drop changes as it falls.
+OPTIONS:
+    constant  - Same speed the whole way
+    gravity   - Starts slow and accelerates
+    brake     - Starts fast and slows down
+    swell     - Speeds up in the middle and slows down at both ends
+";
+
+const HELP_CURVE_STRENGTH: &str = "Set how many times slower a drop is at the slowest point of
+its speed curve, from 1 to 100. Has no effect with the constant speed curve.
+";
+
+const HELP_FPS: &str = "Set the highest number of frames drawn per second.
+Nothing is drawn while no Rain is moving, so a low value saves CPU and a
+high value makes fast Rain smoother.
+";
+
+const HELP_NO_SYNC: &str = "Don't wrap frames in synchronized update sequences.
+Synchronized updates stop tearing on terminals that support them, turn
+them off if the terminal shows stray characters.
+";
+
+const HELP_REDRAW_THRESHOLD: &str = "Set the fraction of the screen that has to change before
+the whole screen is redrawn instead of just the changed cells.
+Takes a value between 0 and 1, 1 never redraws the whole screen.
+";
+
+const HELP_SCREENSAVER: &str = "Exit on any key press, mouse movement, paste or focus change.
+Takes an optional exit status to exit with, defaults to 0.
+";
+
+const HELP_DURATION: &str = "Exit after running for this long.
+OPTIONS:
+    30s,
+    500ms,
+    2m,
+    1h30m
+";
+
+const HELP_FRAMES: &str = "Exit after drawing this many frames.
+";
+
+const HELP_DRAIN: &str = "Let the drops that are falling finish before exiting at the end of
+--duration or --frames, instead of exiting right away.
+";
+
+const HELP_MESSAGE: &str = "Hide a message behind the Rain, the drops reveal it as they fall
+through it. Each line is centered on the screen.
+";
+
+const HELP_MESSAGE_FILE: &str = "Same as --message but reads the message from a file.
+";
+
+const HELP_MESSAGE_COLOR: &str = "Set the color of the revealed message.
+OPTIONS:
+    white,
+    red,
+    blue,
+    green,
+    r,g,b,
+    #RRGGBB
+";
+
+const HELP_CLOCK: &str = "Show the time in big digits in the middle of the screen.
+";
+
+const HELP_CLOCK_COLOR: &str = "Set the color of the clock and the countdown.
+OPTIONS:
+    white,
+    red,
+    blue,
+    green,
+    r,g,b,
+    #RRGGBB
+";
+
+const HELP_COUNTDOWN: &str = "Count down from this long in big digits. The Rain speeds up
+as time runs low and the digits flash once it is up.
+OPTIONS:
+    90s,
+    25m,
+    1h30m
+";
+
+const HELP_BELL: &str = "Ring the terminal bell when the countdown is up.
+";
+
+const HELP_NO_INTRO: &str = "Skip the intro set in the [intro] table of the config.
+";
+
+const HELP_MASK: &str = "Keep the Rain inside this text, drawn in big block letters that
+grow to fill the screen. Each line is centered on the screen.
+";
+
+const HELP_MASK_FILE: &str = "Keep the Rain inside a shape drawn in ASCII art in a file.
+Every character other than a space is part of the shape.
+";
+
+const HELP_INVERT_MASK: &str = "Keep the Rain outside of the --mask shape instead of inside.
+";
+
+const HELP_IMAGE: &str = "Light the Rain up with a picture stretched over the screen.
+Reads PPM and PGM images, and PNG when built with the png feature.
+";
+
+const HELP_IMAGE_MODE: &str = "Set what the --image picture does to the Rain.
+OPTIONS:
+    brightness  - Rain lights up over bright parts and stays dim over dark ones
+    color       - Rain takes on the colors of the picture
+";
+
+const HELP_SOURCE: &str = "Take the Rain characters from a text file, or from standard input
+with -, instead of the character group. The drops spell out the text in order,
+and text piped in keeps coming as it is written.
+";
+
+const HELP_COLUMN_GAP: &str = "Set how many empty columns are left between streams of Rain.
+Counted in characters, so a gap of 1 with double width characters leaves
+two terminal columns empty.
+";
+
+const HELP_SPARSE: &str = "Keep a random fraction of columns empty for as long as the
+Rain runs. Takes a value between 0 and 1, defaults to 0.5 when no value
+is given.
+";
+
 #[derive(Debug, Parser)]
 #[command(
     author = AUTHOR,
@@ -374,8 +612,14 @@ pub struct Cli {
     pub head: String,
     #[arg(short, long, help = HELP_DIRECTION, default_value = "south")]
     pub direction: Direction,
-    #[arg(short = 'S', long, default_value_t = format!("{MAXSPEED},{MINSPEED}"))]
-    pub speed: String,
+    #[arg(short = 'S', long, help = HELP_SPEED, default_value = DEFAULT_SPEED)]
+    pub speed: Speed,
+    #[arg(long, help = HELP_HORIZONTAL_SPEED)]
+    pub horizontal_speed: Option<Speed>,
+    #[arg(long, help = HELP_SPEED_CURVE, default_value = "constant")]
+    pub speed_curve: SpeedCurve,
+    #[arg(long, help = HELP_CURVE_STRENGTH, default_value_t = 3.0, value_parser = parse_curve_strength)]
+    pub curve_strength: f32,
     #[arg(
         short = 'D',
         long,
@@ -383,6 +627,78 @@ pub struct Cli {
         default_value_t = false
     )]
     pub display_group: bool,
+    #[arg(long, help = HELP_MIN_LENGTH, default_value = "4")]
+    pub min_length: TrailLength,
+    #[arg(long, help = HELP_MAX_LENGTH)]
+    pub max_length: Option<TrailLength>,
+    #[arg(long, help = HELP_LENGTH_DISTRIBUTION, default_value = "uniform")]
+    pub length_distribution: Distribution,
+    #[arg(long, help = HELP_DENSITY, default_value_t = 1.0, value_parser = parse_fraction)]
+    pub density: f32,
+    #[arg(long, help = HELP_FPS, default_value_t = 30, value_parser = parse_fps)]
+    pub fps: u32,
+    #[arg(long, help = HELP_NO_SYNC, default_value_t = false)]
+    pub no_sync: bool,
+    #[arg(long, help = HELP_REDRAW_THRESHOLD, default_value_t = 0.5, value_parser = parse_fraction)]
+    pub redraw_threshold: f32,
+    #[arg(long, help = HELP_COLUMN_GAP, default_value_t = 0, value_parser = parse_column_gap)]
+    pub column_gap: usize,
+    #[arg(
+        long,
+        help = HELP_SPARSE,
+        num_args = 0..=1,
+        default_missing_value = "0.5",
+        value_parser = parse_fraction
+    )]
+    pub sparse: Option<f32>,
+    #[arg(
+        long,
+        help = HELP_SCREENSAVER,
+        value_name = "STATUS",
+        num_args = 0..=1,
+        default_missing_value = "0"
+    )]
+    pub screensaver: Option<u8>,
+    #[arg(long, help = HELP_DURATION, value_parser = parse_duration)]
+    pub duration: Option<Duration>,
+    #[arg(long, help = HELP_FRAMES, value_parser = clap::value_parser!(u64).range(1..))]
+    pub frames: Option<u64>,
+    #[arg(long, help = HELP_DRAIN, default_value_t = false)]
+    pub drain: bool,
+    #[arg(long, help = HELP_MESSAGE, conflicts_with = "message_file")]
+    pub message: Option<String>,
+    #[arg(long, help = HELP_MESSAGE_FILE, value_name = "PATH")]
+    pub message_file: Option<PathBuf>,
+    #[arg(long, help = HELP_MESSAGE_COLOR, default_value_t = String::from("white"))]
+    pub message_color: String,
+    #[arg(long, help = HELP_CLOCK, default_value_t = false)]
+    pub clock: bool,
+    #[arg(long, help = HELP_CLOCK_COLOR, default_value_t = String::from("white"))]
+    pub clock_color: String,
+    #[arg(long, help = HELP_COUNTDOWN, value_parser = parse_duration, conflicts_with = "clock")]
+    pub countdown: Option<Duration>,
+    #[arg(long, help = HELP_BELL, default_value_t = false, requires = "countdown")]
+    pub bell: bool,
+    #[arg(long, help = HELP_NO_INTRO, default_value_t = false)]
+    pub no_intro: bool,
+    #[arg(long, help = HELP_MASK, conflicts_with = "mask_file")]
+    pub mask: Option<String>,
+    #[arg(long, help = HELP_MASK_FILE, value_name = "PATH")]
+    pub mask_file: Option<PathBuf>,
+    #[arg(long, help = HELP_INVERT_MASK, default_value_t = false)]
+    pub invert_mask: bool,
+    #[arg(long, help = HELP_IMAGE, value_name = "PATH")]
+    pub image: Option<PathBuf>,
+    #[arg(long, help = HELP_IMAGE_MODE, default_value = "brightness")]
+    pub image_mode: ImageMode,
+    #[arg(long, help = HELP_SOURCE, value_name = "PATH")]
+    pub source: Option<PathBuf>,
+    /// Key bindings, only set from the config
+    #[arg(skip)]
+    pub keys: KeyMap,
+    /// Lines typed out before the rain starts, only set from the config
+    #[arg(skip)]
+    pub intro: Option<Script>,
 }
 
 impl Cli {
@@ -397,22 +713,171 @@ impl Cli {
         into_color(&self.head)
     }
 
+    pub fn clock_color(&self) -> (u8, u8, u8) {
+        into_color(&self.clock_color)
+    }
+
+    pub fn message_color(&self) -> (u8, u8, u8) {
+        into_color(&self.message_color)
+    }
+
     pub fn shade_gradient_color(&self) -> (u8, u8, u8) {
         into_color(&self.shade_gradient)
     }
 
-    pub fn speed(&self) -> (u64, u64) {
-        match self.speed.into_tuple() {
-            Ok((max, min)) => (max, min),
-            _ => (MAXSPEED, MINSPEED),
+    pub fn speed_for(&self, direction: Direction) -> Speed {
+        match direction {
+            Direction::Up | Direction::Down => self.speed,
+            Direction::Left | Direction::Right => self.horizontal_speed.unwrap_or(self.speed),
         }
     }
-    pub fn speed_range(&self) -> std::ops::Range<u64> {
-        let (max, min) = self.speed();
-        max..min
+}
+
+/// Parses a number between 0 and 1.
+pub fn parse_fraction(value: &str) -> Result<f32, String> {
+    value
+        .trim()
+        .parse::<f32>()
+        .map_err(|_| fraction_error(value))
+        .and_then(check_fraction)
+}
+
+/// Checks a number from the config the same way as `parse_fraction`.
+pub fn check_fraction(fraction: f32) -> Result<f32, String> {
+    if (0.0..=1.0).contains(&fraction) {
+        Ok(fraction)
+    } else {
+        Err(fraction_error(fraction))
+    }
+}
+
+fn fraction_error(value: impl std::fmt::Display) -> String {
+    format!("must be a number between 0 and 1, got: {value}")
+}
+
+/// Strongest speed curve, any slower and drops at the slow end of the curve would barely move.
+const MAX_CURVE_STRENGTH: f32 = 100.0;
+
+/// Parses a slowdown factor between 1 and `MAX_CURVE_STRENGTH`.
+pub fn parse_curve_strength(value: &str) -> Result<f32, String> {
+    value
+        .trim()
+        .parse::<f32>()
+        .map_err(|_| curve_strength_error(value))
+        .and_then(check_curve_strength)
+}
+
+/// Checks a slowdown factor from the config the same way as `parse_curve_strength`.
+pub fn check_curve_strength(strength: f32) -> Result<f32, String> {
+    if (1.0..=MAX_CURVE_STRENGTH).contains(&strength) {
+        Ok(strength)
+    } else {
+        Err(curve_strength_error(strength))
+    }
+}
+
+fn curve_strength_error(value: impl std::fmt::Display) -> String {
+    format!("must be a number between 1 and {MAX_CURVE_STRENGTH}, got: {value}")
+}
+
+/// Highest frame rate the rain can be capped at.
+const MAX_FPS: u32 = 1000;
+
+/// Parses a frame rate between 1 and `MAX_FPS`.
+pub fn parse_fps(value: &str) -> Result<u32, String> {
+    value
+        .trim()
+        .parse::<u32>()
+        .map_err(|_| fps_error(value))
+        .and_then(check_fps)
+}
+
+/// Checks a frame rate from the config the same way as `parse_fps`.
+pub fn check_fps(fps: u32) -> Result<u32, String> {
+    if (1..=MAX_FPS).contains(&fps) {
+        Ok(fps)
+    } else {
+        Err(fps_error(fps))
     }
 }
 
+fn fps_error(value: impl std::fmt::Display) -> String {
+    format!("must be a number between 1 and {MAX_FPS}, got: {value}")
+}
+
+/// Widest gap between streams, wider than any terminal so it still leaves a single stream.
+const MAX_COLUMN_GAP: usize = 1000;
+
+/// Parses a gap of up to `MAX_COLUMN_GAP` columns.
+pub fn parse_column_gap(value: &str) -> Result<usize, String> {
+    value
+        .trim()
+        .parse::<usize>()
+        .map_err(|_| column_gap_error(value))
+        .and_then(check_column_gap)
+}
+
+/// Checks a gap from the config the same way as `parse_column_gap`.
+pub fn check_column_gap(gap: usize) -> Result<usize, String> {
+    if gap <= MAX_COLUMN_GAP {
+        Ok(gap)
+    } else {
+        Err(column_gap_error(gap))
+    }
+}
+
+fn column_gap_error(value: impl std::fmt::Display) -> String {
+    format!("must be a number between 0 and {MAX_COLUMN_GAP}, got: {value}")
+}
+
+/// Longest length of time taken, a year. Anything longer is more likely a typo, and far enough
+/// out it no longer fits in an `Instant`.
+pub const MAX_DURATION: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+/// Parses a length of time like `30s`, `500ms`, `2m` or `1h30m`. A plain number is in seconds.
+/// Lengths over `MAX_DURATION` are refused.
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let duration = parse_any_duration(value)?;
+    if duration > MAX_DURATION {
+        return Err(format!(
+            "Invalid duration: {value} (can be at most {}h)",
+            MAX_DURATION.as_secs() / 3600
+        ));
+    }
+    Ok(duration)
+}
+
+fn parse_any_duration(value: &str) -> Result<Duration, String> {
+    let error = || format!("Invalid duration: {value} (expected e.g. 30s, 500ms, 2m or 1h30m)");
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<f64>() {
+        return Duration::try_from_secs_f64(seconds).map_err(|_| error());
+    }
+
+    if value.is_empty() {
+        return Err(error());
+    }
+
+    let is_number = |c: char| c.is_ascii_digit() || c == '.';
+    let mut seconds = 0.0;
+    let mut rest = value;
+    while !rest.is_empty() {
+        let (number, tail) = rest.split_at(rest.find(|c| !is_number(c)).ok_or_else(error)?);
+        let (unit, tail) = tail.split_at(tail.find(is_number).unwrap_or(tail.len()));
+        let number: f64 = number.parse().map_err(|_| error())?;
+        seconds += number
+            * match unit {
+                "ms" => 0.001,
+                "s" => 1.0,
+                "m" => 60.0,
+                "h" => 3600.0,
+                _ => return Err(error()),
+            };
+        rest = tail;
+    }
+    Duration::try_from_secs_f64(seconds).map_err(|_| error())
+}
+
 pub fn into_color(value: &str) -> (u8, u8, u8) {
     if let Ok((r, g, b)) = StrTuple::<(u8, u8, u8)>::into_tuple(value) {
         return (r, g, b);
@@ -425,19 +890,6 @@ pub fn into_color(value: &str) -> (u8, u8, u8) {
     }
 }
 
-impl StrTuple<(u64, u64)> for &str {
-    type Error = std::num::ParseIntError;
-    fn into_tuple(self) -> Result<(u64, u64), Self::Error> {
-        let mut nums = Vec::new();
-        for num in self.split(',') {
-            nums.push(num.parse::<u64>()?);
-        }
-        let a = nums[0];
-        let b = nums[1];
-        Ok((a, b))
-    }
-}
-
 impl StrTuple<(u8, u8, u8)> for &str {
     type Error = std::num::ParseIntError;
     fn into_tuple(self) -> Result<(u8, u8, u8), Self::Error> {
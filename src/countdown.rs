@@ -0,0 +1,164 @@
+use crate::{Cell, cli::MAX_DURATION, font};
+use crossterm::style::Color;
+use std::time::{Duration, Instant};
+
+/// Time left on a timer in big digits in the middle of the screen. The rain speeds up and the
+/// digits turn red as time runs out, and once it is up the digits flash.
+#[derive(Debug)]
+pub struct Countdown {
+    /// When the time is up
+    end: Instant,
+    /// Time left when the timer starts to run low
+    low: Duration,
+    color: Color,
+    /// Whole seconds left, whether the digits are on and their color, as they are on the screen
+    shown: Option<(u64, bool, Color)>,
+    bitmap: Vec<Vec<bool>>,
+    /// How many times faster than normal the rain is running
+    speed: f32,
+    /// Speed change the rain hasn't picked up yet
+    speed_change: Option<f32>,
+    /// Ring the bell when the time is up
+    bell: bool,
+    /// Set when the bell has to be rung
+    ring: bool,
+}
+
+impl Countdown {
+    /// The timer runs low for the last minute, or the last fifth of shorter timers
+    const LOW: Duration = Duration::from_secs(60);
+    /// How many times faster than normal the rain is when the time is up
+    const TOP_SPEED: f32 = 3.0;
+    /// Color the digits turn as the timer runs low
+    const LOW_COLOR: (u8, u8, u8) = (255, 0, 0);
+    /// Time the digits are on and then off once the time is up
+    const FLASH: Duration = Duration::from_millis(500);
+
+    /// Starts a timer of `duration`, up to `MAX_DURATION` like `--countdown` takes.
+    pub fn new(duration: Duration, color: Color, bell: bool, now: Instant) -> Self {
+        let duration = duration.min(MAX_DURATION);
+        Self {
+            end: now + duration,
+            low: Self::LOW.min(duration / 5),
+            color,
+            shown: None,
+            bitmap: Vec::new(),
+            speed: 1.0,
+            speed_change: None,
+            bell,
+            ring: false,
+        }
+    }
+
+    pub fn remaining(&self, now: Instant) -> Duration {
+        self.end.saturating_duration_since(now)
+    }
+
+    /// How far into running low the timer is with `remaining` left, from 0 until it starts
+    /// running low to 1 when the time is up.
+    fn urgency(&self, remaining: Duration) -> f32 {
+        if remaining >= self.low {
+            0.0
+        } else {
+            1.0 - remaining.as_secs_f32() / self.low.as_secs_f32()
+        }
+    }
+
+    /// Catches up with the time at `now` and returns whether the screen changes.
+    pub fn tick(&mut self, now: Instant) -> bool {
+        let remaining = self.remaining(now);
+        // Rounded up, so the timer starts on the full time and reads zero once it is up.
+        let seconds = remaining.as_secs() + (remaining.subsec_nanos() > 0) as u64;
+        let on = seconds > 0 || {
+            let over = now.saturating_duration_since(self.end);
+            (over.as_nanos() / Self::FLASH.as_nanos()).is_multiple_of(2)
+        };
+        let urgency = self.urgency(Duration::from_secs(seconds));
+        let color = fade(self.color, Self::LOW_COLOR.into(), urgency);
+        if self.shown == Some((seconds, on, color)) {
+            return false;
+        }
+
+        if self.shown.is_none_or(|(shown, ..)| shown != seconds) {
+            let (hours, minutes) = (seconds / 3600, seconds / 60 % 60);
+            let text = if hours > 0 {
+                format!("{hours}:{minutes:02}:{:02}", seconds % 60)
+            } else {
+                format!("{minutes:02}:{:02}", seconds % 60)
+            };
+            self.bitmap = font::bitmap(&text);
+        }
+        if seconds == 0 && self.shown.is_some_and(|(shown, ..)| shown > 0) {
+            self.ring = self.bell;
+        }
+        self.shown = Some((seconds, on, color));
+
+        let speed = 1.0 + (Self::TOP_SPEED - 1.0) * urgency;
+        if speed != self.speed {
+            self.speed_change = Some(speed / self.speed);
+            self.speed = speed;
+        }
+        true
+    }
+
+    /// When the screen changes next.
+    pub fn next_change(&self, now: Instant) -> Instant {
+        let remaining = self.remaining(now);
+        if remaining.is_zero() {
+            let over = now.saturating_duration_since(self.end);
+            let flashes = (over.as_nanos() / Self::FLASH.as_nanos()) as u32 + 1;
+            return self.end + Self::FLASH * flashes;
+        }
+        let fraction = Duration::from_nanos(remaining.subsec_nanos() as u64);
+        now + if fraction.is_zero() {
+            Duration::from_secs(1)
+        } else {
+            fraction
+        }
+    }
+
+    /// Factor to change the speed of the rain by, if it changed since the last time.
+    pub fn take_speed_change(&mut self) -> Option<f32> {
+        self.speed_change.take()
+    }
+
+    /// Whether the bell has to be rung, it only rings once when the time is up.
+    pub fn take_ring(&mut self) -> bool {
+        std::mem::take(&mut self.ring)
+    }
+
+    /// Draws the time left over `cells`, leaving it out when it doesn't fit.
+    pub fn draw(&self, cells: &mut [Cell], width: usize, height: usize, char_width: usize) {
+        let Some((_, true, color)) = self.shown else {
+            return;
+        };
+        if font::fits(&self.bitmap, width, height, char_width) {
+            font::draw_centered(&self.bitmap, cells, width, char_width, color);
+        }
+    }
+}
+
+/// Color `t` of the way from `from` to `to`.
+fn fade(from: Color, to: Color, t: f32) -> Color {
+    let (
+        Color::Rgb {
+            r: fr,
+            g: fg,
+            b: fb,
+        },
+        Color::Rgb {
+            r: tr,
+            g: tg,
+            b: tb,
+        },
+    ) = (from, to)
+    else {
+        return from;
+    };
+    let mix = |from: u8, to: u8| (from as f32 + (to as f32 - from as f32) * t).round() as u8;
+    Color::Rgb {
+        r: mix(fr, tr),
+        g: mix(fg, tg),
+        b: mix(fb, tb),
+    }
+}
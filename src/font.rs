@@ -0,0 +1,112 @@
+use crate::Cell;
+use crossterm::style::Color;
+
+/// Rows of pixels in a glyph.
+pub const HEIGHT: usize = 5;
+
+/// Big block glyphs, `#` marks a pixel that is set.
+const GLYPHS: &[(char, [&str; HEIGHT])] = &[
+    ('0', ["###", "# #", "# #", "# #", "###"]),
+    ('1', ["  #", "  #", "  #", "  #", "  #"]),
+    ('2', ["###", "  #", "###", "#  ", "###"]),
+    ('3', ["###", "  #", "###", "  #", "###"]),
+    ('4', ["# #", "# #", "###", "  #", "  #"]),
+    ('5', ["###", "#  ", "###", "  #", "###"]),
+    ('6', ["###", "#  ", "###", "# #", "###"]),
+    ('7', ["###", "  #", "  #", "  #", "  #"]),
+    ('8', ["###", "# #", "###", "# #", "###"]),
+    ('9', ["###", "# #", "###", "  #", "###"]),
+    ('A', ["###", "# #", "###", "# #", "# #"]),
+    ('B', ["## ", "# #", "## ", "# #", "## "]),
+    ('C', ["###", "#  ", "#  ", "#  ", "###"]),
+    ('D', ["## ", "# #", "# #", "# #", "## "]),
+    ('E', ["###", "#  ", "## ", "#  ", "###"]),
+    ('F', ["###", "#  ", "## ", "#  ", "#  "]),
+    ('G', ["###", "#  ", "# #", "# #", "###"]),
+    ('H', ["# #", "# #", "###", "# #", "# #"]),
+    ('I', ["###", " # ", " # ", " # ", "###"]),
+    ('J', ["  #", "  #", "  #", "# #", "###"]),
+    ('K', ["# #", "# #", "## ", "# #", "# #"]),
+    ('L', ["#  ", "#  ", "#  ", "#  ", "###"]),
+    ('M', ["# #", "###", "###", "# #", "# #"]),
+    ('N', ["###", "# #", "# #", "# #", "# #"]),
+    ('O', ["###", "# #", "# #", "# #", "###"]),
+    ('P', ["###", "# #", "###", "#  ", "#  "]),
+    ('Q', ["###", "# #", "# #", "###", "  #"]),
+    ('R', ["###", "# #", "## ", "# #", "# #"]),
+    ('S', ["###", "#  ", "###", "  #", "###"]),
+    ('T', ["###", " # ", " # ", " # ", " # "]),
+    ('U', ["# #", "# #", "# #", "# #", "###"]),
+    ('V', ["# #", "# #", "# #", "# #", " # "]),
+    ('W', ["# #", "# #", "###", "###", "# #"]),
+    ('X', ["# #", "# #", " # ", "# #", "# #"]),
+    ('Y', ["# #", "# #", "###", " # ", " # "]),
+    ('Z', ["###", "  #", " # ", "#  ", "###"]),
+    (':', [" ", "#", " ", "#", " "]),
+    ('.', [" ", " ", " ", " ", "#"]),
+    ('!', ["#", "#", "#", " ", "#"]),
+    ('?', ["###", "  #", " ##", "   ", " # "]),
+    ('-', ["   ", "   ", "###", "   ", "   "]),
+    (' ', ["  ", "  ", "  ", "  ", "  "]),
+];
+
+/// Pixels of `text` in the block font, one row after the other with a blank column between
+/// glyphs. Letters are all upper case and characters the font doesn't have are left out.
+pub fn bitmap(text: &str) -> Vec<Vec<bool>> {
+    let mut rows = vec![Vec::new(); HEIGHT];
+    let glyphs = text.chars().filter_map(|c| {
+        let c = c.to_ascii_uppercase();
+        GLYPHS.iter().find(|(glyph, _)| *glyph == c)
+    });
+    for (i, (_, glyph)) in glyphs.enumerate() {
+        for (row, pixels) in rows.iter_mut().zip(glyph) {
+            if i > 0 {
+                row.push(false);
+            }
+            row.extend(pixels.chars().map(|pixel| pixel == '#'));
+        }
+    }
+    rows
+}
+
+/// Cells a pixel takes up, terminal cells are about twice as high as they are wide.
+pub fn pixel_width(char_width: usize) -> usize {
+    if char_width == 1 { 2 } else { 1 }
+}
+
+/// Block that fills a cell.
+pub fn block(char_width: usize) -> char {
+    if char_width == 1 { '█' } else { '⬛' }
+}
+
+/// Whether `bitmap` fits on a screen of `width` by `height` cells.
+pub fn fits(bitmap: &[Vec<bool>], width: usize, height: usize, char_width: usize) -> bool {
+    let columns = bitmap.first().map_or(0, Vec::len) * pixel_width(char_width);
+    columns <= width && bitmap.len() <= height
+}
+
+/// Draws the set pixels of `bitmap` as blocks of `color` in the middle of a screen of `width`
+/// cells, leaving whatever is under the other pixels as it is. Pixels off the screen are cut off.
+pub fn draw_centered(
+    bitmap: &[Vec<bool>],
+    cells: &mut [Cell],
+    width: usize,
+    char_width: usize,
+    color: Color,
+) {
+    let height = cells.len() / width.max(1);
+    let pixel_width = pixel_width(char_width);
+    let cell = Cell::new(block(char_width)).color(color);
+    let columns = bitmap.first().map_or(0, Vec::len) * pixel_width;
+    let left = width.saturating_sub(columns) / 2;
+    let top = height.saturating_sub(bitmap.len()) / 2;
+    for (y, row) in (top..height).zip(bitmap) {
+        for (i, _) in row.iter().enumerate().filter(|(_, set)| **set) {
+            for x in (left + i * pixel_width..).take(pixel_width) {
+                if x < width {
+                    cells[y * width + x] = cell;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use crate::{Cell, Clock, Direction, Rain, cli, fit_width, keys::Action, render::FrameStats};
+use crossterm::style::Color;
+use ezemoji::GroupKind;
+use std::time::{Duration, Instant};
+
+/// Overlay showing what the rain is set to, how fast frames are drawn and which keys do what.
+/// It is drawn over a copy of the screen buffer, so hiding it again is just another frame of rain
+/// for the renderer to diff against.
+#[derive(Debug, Default)]
+pub struct Hud {
+    pub visible: bool,
+    /// Time and number of frames drawn when the frame rate was last worked out
+    sample: Option<(Instant, u64)>,
+    /// Frames drawn per second
+    fps: f32,
+}
+
+impl Hud {
+    /// How long frames are counted for each time the frame rate is worked out
+    const SAMPLE_INTERVAL: Duration = Duration::from_millis(500);
+    /// Cells left between the edge of the screen and the overlay
+    const MARGIN: usize = 1;
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+        self.sample = None;
+    }
+
+    /// Counts the frames the render thread has drawn since the last sample.
+    fn sample(&mut self, now: Instant, frames: u64) {
+        let Some((since, drawn)) = self.sample else {
+            self.sample = Some((now, frames));
+            return;
+        };
+        let elapsed = now.saturating_duration_since(since);
+        if elapsed >= Self::SAMPLE_INTERVAL {
+            self.fps = frames.saturating_sub(drawn) as f32 / elapsed.as_secs_f32();
+            self.sample = Some((now, frames));
+        }
+    }
+
+    /// Lines of the overlay as label and value.
+    fn lines<const LENGTH: usize>(
+        &self,
+        rain: &Rain<LENGTH>,
+        settings: &cli::Cli,
+        stats: FrameStats,
+    ) -> Vec<(String, String)> {
+        let group = match settings.group.name() {
+            GroupKind::Custom(name) => name.to_string(),
+            name => format!("{name:?}"),
+        };
+        let shade = if settings.shade {
+            format!("fading to {}", settings.shade_gradient)
+        } else {
+            "off".to_string()
+        };
+        // The speed it was set to, the rain runs at that times the speed scale.
+        let speed = |speed: crate::Speed| {
+            let mut text = format!("{:.1}..{:.1} cells/s", speed.min, speed.max);
+            if rain.speed_scale != 1.0 {
+                text.push_str(&format!(" x{:.2}", rain.speed_scale));
+            }
+            text
+        };
+        let fps = if rain.is_paused() {
+            "paused".to_string()
+        } else {
+            format!("{:.1} of {}", self.fps, settings.fps)
+        };
+
+        let mut lines = vec![
+            ("group".to_string(), group),
+            ("direction".to_string(), format!("{:?}", settings.direction)),
+            (
+                "color".to_string(),
+                format!("{}, head {}", settings.color, settings.head),
+            ),
+            ("shade".to_string(), shade),
+            (
+                "speed".to_string(),
+                speed(settings.speed_for(Direction::Down)),
+            ),
+        ];
+        if settings.horizontal_speed.is_some() {
+            let sideways = speed(settings.speed_for(Direction::Right));
+            lines.push(("sideways".to_string(), sideways));
+        }
+        lines.extend([
+            ("fps".to_string(), fps),
+            ("redrawn".to_string(), format!("{} cells", stats.cells)),
+            (String::new(), String::new()),
+        ]);
+        for action in Action::ALL {
+            let mut keys = settings.keys.keys(action).map(|key| key.to_string());
+            let Some(first) = keys.next() else {
+                continue;
+            };
+            let keys = keys.fold(first, |all, key| all + " " + &key);
+            // Actions that share a description, like the arrow keys, go on one line.
+            match lines.last_mut() {
+                Some((label, description)) if description == action.description() => {
+                    label.push(' ');
+                    label.push_str(&keys);
+                }
+                _ => lines.push((keys, action.description().to_string())),
+            }
+        }
+        lines
+    }
+
+    /// Draws the overlay over `cells` in the top left corner, cutting it off where the screen ends.
+    pub fn draw<const LENGTH: usize>(
+        &mut self,
+        cells: &mut [Cell],
+        rain: &Rain<LENGTH>,
+        settings: &cli::Cli,
+        stats: FrameStats,
+    ) {
+        self.sample(rain.clock.now(), stats.frames);
+        let lines = self.lines(rain, settings, stats);
+
+        let (r, g, b) = settings.rain_color();
+        let label_color = Color::Rgb { r, g, b };
+        let (r, g, b) = settings.head_color();
+        let value_color = Color::Rgb { r, g, b };
+        let label_width = lines
+            .iter()
+            .map(|(label, _)| label.chars().count())
+            .max()
+            .unwrap_or(0);
+        let panel_width = lines
+            .iter()
+            .map(|(_, value)| label_width + 2 + value.chars().count())
+            .max()
+            .unwrap_or(0)
+            + 2;
+
+        // A blank row above and below the text and a blank cell either side of it keep the rain
+        // from running into the letters.
+        let rows = std::iter::once(None)
+            .chain(lines.iter().map(Some))
+            .chain(std::iter::once(None));
+        for (row, line) in rows.enumerate() {
+            let y = Self::MARGIN + row;
+            if y >= rain.height {
+                break;
+            }
+            let mut text = vec![Cell::default(); panel_width];
+            if let Some((label, value)) = line {
+                let gap = std::iter::repeat_n(
+                    (' ', label_color),
+                    label_width + 2 - label.chars().count(),
+                );
+                let label = label.chars().map(|c| (c, label_color));
+                let value = value.chars().map(|c| (c, value_color));
+                for (cell, (c, color)) in text[1..].iter_mut().zip(label.chain(gap).chain(value)) {
+                    if c != ' ' {
+                        *cell = Cell::new(fit_width(c, rain.char_width)).color(color);
+                    }
+                }
+            }
+            for (x, cell) in text.into_iter().enumerate() {
+                let x = Self::MARGIN + x;
+                if x >= rain.width {
+                    break;
+                }
+                cells[y * rain.width + x] = cell;
+            }
+        }
+    }
+}
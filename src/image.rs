@@ -0,0 +1,250 @@
+use crossterm::style::Color;
+use std::str::FromStr;
+
+/// Pixels of a picture loaded from a file, row after row.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<[u8; 3]>,
+}
+
+impl Image {
+    pub fn load(path: &std::path::Path) -> Result<Self, String> {
+        let bytes = std::fs::read(path).map_err(|err| err.to_string())?;
+        Self::decode(&bytes)
+    }
+
+    /// Reads a PPM or PGM image, or a PNG one when built with the `png` feature.
+    pub fn decode(bytes: &[u8]) -> Result<Self, String> {
+        const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+        if bytes.starts_with(PNG_SIGNATURE) {
+            return decode_png(bytes);
+        }
+        if bytes.first() == Some(&b'P') {
+            return decode_pnm(bytes);
+        }
+        Err("not a PPM, PGM or PNG image".into())
+    }
+}
+
+/// Reads the plain (`P2`, `P3`) and binary (`P5`, `P6`) flavors of PGM and PPM.
+fn decode_pnm(bytes: &[u8]) -> Result<Image, String> {
+    let mut pos = 0;
+    // Header fields are separated by whitespace, comments run from `#` to the end of the line.
+    let token = |pos: &mut usize| -> Option<&[u8]> {
+        loop {
+            match bytes.get(*pos)? {
+                b'#' => {
+                    while bytes.get(*pos).is_some_and(|b| *b != b'\n') {
+                        *pos += 1;
+                    }
+                }
+                b if b.is_ascii_whitespace() => *pos += 1,
+                _ => break,
+            }
+        }
+        let start = *pos;
+        while bytes.get(*pos).is_some_and(|b| !b.is_ascii_whitespace()) {
+            *pos += 1;
+        }
+        Some(&bytes[start..*pos])
+    };
+    let number = |pos: &mut usize, what: &str| -> Result<usize, String> {
+        token(pos)
+            .and_then(|token| std::str::from_utf8(token).ok()?.parse().ok())
+            .ok_or_else(|| format!("invalid {what} in image header"))
+    };
+
+    let (channels, binary) = match token(&mut pos) {
+        Some(b"P2") => (1, false),
+        Some(b"P3") => (3, false),
+        Some(b"P5") => (1, true),
+        Some(b"P6") => (3, true),
+        _ => return Err("only PGM (P2, P5) and PPM (P3, P6) images are supported".into()),
+    };
+    let width = number(&mut pos, "width")?;
+    let height = number(&mut pos, "height")?;
+    let max = number(&mut pos, "maximum value")?;
+    if !(1..=u16::MAX as usize).contains(&max) {
+        return Err("invalid maximum value in image header".into());
+    }
+
+    let count = width
+        .checked_mul(height)
+        .and_then(|pixels| pixels.checked_mul(channels))
+        .ok_or("image is too big")?;
+    let samples: Vec<usize> = if binary {
+        // A single whitespace character separates the header from the data.
+        let data = bytes.get(pos + 1..).unwrap_or_default();
+        if max < 256 {
+            data.iter().take(count).map(|b| *b as usize).collect()
+        } else {
+            data.chunks_exact(2)
+                .take(count)
+                .map(|b| u16::from_be_bytes([b[0], b[1]]) as usize)
+                .collect()
+        }
+    } else {
+        (0..count)
+            .map_while(|_| number(&mut pos, "sample").ok())
+            .collect()
+    };
+    if samples.len() < count {
+        return Err("image data is cut short".into());
+    }
+
+    let scale = |sample: usize| (sample.min(max) * 255 / max) as u8;
+    let pixels = samples
+        .chunks_exact(channels)
+        .map(|pixel| match pixel {
+            [gray] => [scale(*gray); 3],
+            [r, g, b] => [scale(*r), scale(*g), scale(*b)],
+            _ => unreachable!("pixels have one or three channels"),
+        })
+        .collect();
+    Ok(Image {
+        width,
+        height,
+        pixels,
+    })
+}
+
+#[cfg(feature = "png")]
+fn decode_png(bytes: &[u8]) -> Result<Image, String> {
+    let mut decoder = png::Decoder::new(std::io::Cursor::new(bytes));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(|err| err.to_string())?;
+    let mut buffer = vec![0; reader.output_buffer_size().unwrap_or_default()];
+    let info = reader
+        .next_frame(&mut buffer)
+        .map_err(|err| err.to_string())?;
+    let channels = info.color_type.samples();
+    let pixels = buffer[..info.buffer_size()]
+        .chunks_exact(channels)
+        .map(|pixel| match pixel {
+            [gray] | [gray, _] => [*gray; 3],
+            [r, g, b, ..] => [*r, *g, *b],
+            _ => unreachable!("pixels have one to four channels"),
+        })
+        .collect();
+    Ok(Image {
+        width: info.width as usize,
+        height: info.height as usize,
+        pixels,
+    })
+}
+
+#[cfg(not(feature = "png"))]
+fn decode_png(_: &[u8]) -> Result<Image, String> {
+    Err("PNG images need rusty-rain built with the png feature".into())
+}
+
+/// What the picture does to the rain falling over it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ImageMode {
+    /// Rain lights up over bright parts of the picture and stays dim over dark ones
+    #[default]
+    Brightness,
+    /// Rain takes on the colors of the picture
+    Color,
+}
+
+impl FromStr for ImageMode {
+    type Err = String;
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "brightness" => Ok(Self::Brightness),
+            "color" | "colour" => Ok(Self::Color),
+            _ => Err(format!("Invalid image mode: {value}")),
+        }
+    }
+}
+
+/// Picture stretched over the screen that sets the color of the rain cell by cell.
+#[derive(Debug)]
+pub struct Picture {
+    image: Image,
+    mode: ImageMode,
+    /// Color of the picture under each cell of the screen
+    cells: Vec<[u8; 3]>,
+}
+
+impl Picture {
+    /// How bright rain is over the darkest parts of the picture, so it never goes out entirely
+    const MIN_BRIGHTNESS: f32 = 0.15;
+
+    pub fn new(image: Image, mode: ImageMode) -> Self {
+        Self {
+            image,
+            mode,
+            cells: Vec::new(),
+        }
+    }
+
+    /// Scales the picture to a screen of `width` by `height` cells, each cell taking the average
+    /// of the pixels it covers.
+    pub fn layout(&mut self, width: usize, height: usize) {
+        self.cells.clear();
+        let Image {
+            width: image_width,
+            height: image_height,
+            ref pixels,
+        } = self.image;
+        if image_width == 0 || image_height == 0 {
+            self.cells.resize(width * height, [255; 3]);
+            return;
+        }
+        // Pixels from `start` up to but not including `end` along one side, at least one.
+        let span = |cell: usize, cells: usize, size: usize| {
+            let start = (cell * size / cells).min(size - 1);
+            start..((cell + 1) * size / cells).max(start + 1)
+        };
+        for y in 0..height {
+            let rows = span(y, height, image_height);
+            for x in 0..width {
+                let columns = span(x, width, image_width);
+                let mut sum = [0usize; 3];
+                for row in rows.clone() {
+                    for pixel in &pixels[row * image_width..][columns.clone()] {
+                        for (sum, channel) in sum.iter_mut().zip(pixel) {
+                            *sum += *channel as usize;
+                        }
+                    }
+                }
+                let count = rows.len() * columns.len();
+                self.cells.push(sum.map(|sum| (sum / count) as u8));
+            }
+        }
+    }
+
+    /// Color of rain drawn in `color` in the cell at `idx`, `head` is set for the head of a drop.
+    pub fn tint(&self, idx: usize, color: Color, head: bool) -> Color {
+        let (Some(&[pr, pg, pb]), Color::Rgb { r, g, b }) = (self.cells.get(idx), color) else {
+            return color;
+        };
+        let scale = |channel: u8, by: f32| (channel as f32 * by).round() as u8;
+        match self.mode {
+            ImageMode::Brightness => {
+                let luma = (0.2126 * pr as f32 + 0.7152 * pg as f32 + 0.0722 * pb as f32) / 255.0;
+                let by = Self::MIN_BRIGHTNESS + (1.0 - Self::MIN_BRIGHTNESS) * luma;
+                Color::Rgb {
+                    r: scale(r, by),
+                    g: scale(g, by),
+                    b: scale(b, by),
+                }
+            }
+            // Heads keep their color so the drops stand out against the picture.
+            ImageMode::Color if head => color,
+            ImageMode::Color => {
+                // The picture takes the place of the rain color, dimmed as much as the trail is.
+                let by = r.max(g).max(b) as f32 / 255.0;
+                Color::Rgb {
+                    r: scale(pr, by),
+                    g: scale(pg, by),
+                    b: scale(pb, by),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use crate::{Cell, Clock, cli::parse_duration, fit_width};
+use crossterm::style::Color;
+use std::time::{Duration, Instant};
+
+/// The `[intro]` table of the config.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct IntroConfig {
+    /// Time between two typed characters, like `80ms`
+    pub typing: Option<String>,
+    /// How long a line stays up once it is typed when it doesn't set its own delay
+    pub delay: Option<String>,
+    pub lines: Vec<IntroLine>,
+}
+
+/// A line of the intro, either just the text or the text with its own delay.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+pub enum IntroLine {
+    Text(String),
+    Line { text: String, delay: Option<String> },
+}
+
+/// Lines typed out one after the other before the rain starts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Script {
+    /// Time between two typed characters
+    typing: Duration,
+    /// Text of each line and how long it stays up once it is typed
+    lines: Vec<(String, Duration)>,
+}
+
+impl Script {
+    const TYPING: Duration = Duration::from_millis(100);
+    const DELAY: Duration = Duration::from_millis(1500);
+}
+
+impl TryFrom<&IntroConfig> for Script {
+    type Error = String;
+    fn try_from(config: &IntroConfig) -> Result<Self, Self::Error> {
+        let duration =
+            |value: &Option<String>, default| value.as_deref().map_or(Ok(default), parse_duration);
+        let typing = duration(&config.typing, Self::TYPING)?;
+        let delay = duration(&config.delay, Self::DELAY)?;
+        let lines = config
+            .lines
+            .iter()
+            .map(|line| match line {
+                IntroLine::Text(text) => Ok((text.clone(), delay)),
+                IntroLine::Line { text, delay: own } => Ok((text.clone(), duration(own, delay)?)),
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self { typing, lines })
+    }
+}
+
+/// Where the intro is at a point in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct State {
+    /// Line on the screen
+    line: usize,
+    /// Characters of it typed so far
+    typed: usize,
+    cursor: bool,
+    /// Time until the screen changes
+    next: Duration,
+}
+
+/// Plays a `Script` like someone typing at a terminal: each line is typed out on an empty screen
+/// with a blinking cursor, stays up for its delay and makes way for the next one.
+#[derive(Debug)]
+pub struct Intro {
+    script: Script,
+    pub clock: Box<dyn Clock>,
+    started: Instant,
+    /// Screen with the text on it, kept around to reuse the allocation
+    frame: Vec<Cell>,
+}
+
+impl Intro {
+    /// Time the cursor is on and then off while a line is up
+    const BLINK: Duration = Duration::from_millis(500);
+    /// Cells left between the edge of the screen and the text
+    const MARGIN: usize = 1;
+    /// Cursor for single and double width cells
+    const CURSOR: [char; 2] = ['█', '⬛'];
+
+    pub fn new(script: Script, clock: impl Clock + 'static) -> Self {
+        let started = clock.now();
+        Self {
+            script,
+            clock: Box::new(clock),
+            started,
+            frame: Vec::new(),
+        }
+    }
+
+    pub fn now(&self) -> Instant {
+        self.clock.now()
+    }
+
+    fn state(&self) -> Option<State> {
+        let mut elapsed = self.now().saturating_duration_since(self.started);
+        for (line, (text, delay)) in self.script.lines.iter().enumerate() {
+            let length = text.chars().count();
+            let typing = self.script.typing * length as u32;
+            if elapsed < typing {
+                let typed = (elapsed.as_nanos() / self.script.typing.as_nanos()) as usize;
+                return Some(State {
+                    line,
+                    typed,
+                    cursor: true,
+                    next: self.script.typing * (typed as u32 + 1) - elapsed,
+                });
+            }
+            elapsed -= typing;
+            if elapsed < *delay {
+                let blinks = (elapsed.as_nanos() / Self::BLINK.as_nanos()) as u32;
+                return Some(State {
+                    line,
+                    typed: length,
+                    cursor: blinks.is_multiple_of(2),
+                    next: (Self::BLINK * (blinks + 1)).min(*delay) - elapsed,
+                });
+            }
+            elapsed -= *delay;
+        }
+        None
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.state().is_none()
+    }
+
+    /// When the screen changes next, or now once the intro is over.
+    pub fn next_change(&self) -> Instant {
+        let now = self.now();
+        self.state().map_or(now, |state| now + state.next)
+    }
+
+    /// Draws the intro as it is right now on a screen of `width` by `height` cells, starting in the
+    /// top left corner and cutting it off where the screen ends.
+    pub fn frame(
+        &mut self,
+        width: usize,
+        height: usize,
+        char_width: usize,
+        color: Color,
+    ) -> &[Cell] {
+        self.frame.clear();
+        self.frame.resize(width * height, Cell::default());
+        let Some(state) = self.state() else {
+            return &self.frame;
+        };
+
+        let text = self.script.lines[state.line].0.chars().take(state.typed);
+        let cursor = state
+            .cursor
+            .then_some(Self::CURSOR[(char_width == 2) as usize]);
+        let (mut x, mut y) = (Self::MARGIN, Self::MARGIN);
+        for c in text.chain(cursor) {
+            if c == '\n' {
+                (x, y) = (Self::MARGIN, y + 1);
+                continue;
+            }
+            if x < width && y < height && c != ' ' {
+                self.frame[y * width + x] = Cell::new(fit_width(c, char_width)).color(color);
+            }
+            x += 1;
+        }
+        &self.frame
+    }
+}
@@ -0,0 +1,273 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::str::FromStr;
+
+/// Things a key can be bound to while the rain is running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Quit,
+    Suspend,
+    Pause,
+    Step,
+    SpeedUp,
+    SlowDown,
+    Up,
+    Down,
+    Left,
+    Right,
+    NextGroup,
+    NextColor,
+    ToggleShade,
+    ToggleHud,
+}
+
+impl Action {
+    pub const ALL: [Action; 14] = [
+        Self::Pause,
+        Self::Step,
+        Self::SpeedUp,
+        Self::SlowDown,
+        Self::Up,
+        Self::Down,
+        Self::Left,
+        Self::Right,
+        Self::NextGroup,
+        Self::NextColor,
+        Self::ToggleShade,
+        Self::ToggleHud,
+        Self::Suspend,
+        Self::Quit,
+    ];
+
+    /// What the action does, for the cheat sheet in the HUD.
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::Quit => "quit",
+            Self::Suspend => "suspend",
+            Self::Pause => "pause",
+            Self::Step => "step while paused",
+            Self::SpeedUp => "faster",
+            Self::SlowDown => "slower",
+            Self::Up | Self::Down | Self::Left | Self::Right => "direction",
+            Self::NextGroup => "next group",
+            Self::NextColor => "next color",
+            Self::ToggleShade => "shading",
+            Self::ToggleHud => "this help",
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "quit" => Ok(Self::Quit),
+            "suspend" => Ok(Self::Suspend),
+            "pause" => Ok(Self::Pause),
+            "step" => Ok(Self::Step),
+            "speed-up" => Ok(Self::SpeedUp),
+            "slow-down" => Ok(Self::SlowDown),
+            "up" => Ok(Self::Up),
+            "down" => Ok(Self::Down),
+            "left" => Ok(Self::Left),
+            "right" => Ok(Self::Right),
+            "next-group" => Ok(Self::NextGroup),
+            "next-color" => Ok(Self::NextColor),
+            "toggle-shade" => Ok(Self::ToggleShade),
+            "toggle-hud" => Ok(Self::ToggleHud),
+            _ => Err(format!("Invalid action: {value}")),
+        }
+    }
+}
+
+/// A key together with the modifiers that have to be held down with it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyBinding {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyBinding {
+    fn matches(&self, key: &KeyEvent) -> bool {
+        // Shift is already part of the character, `Q` comes in as shift + Q on some terminals and
+        // as just Q on others.
+        let ignored = match key.code {
+            KeyCode::Char(_) => KeyModifiers::SHIFT,
+            _ => KeyModifiers::NONE,
+        };
+        self.code == key.code && self.modifiers - ignored == key.modifiers - ignored
+    }
+}
+
+impl std::fmt::Display for KeyBinding {
+    /// Writes the key the way it is written in the config.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (modifier, name) in [
+            (KeyModifiers::CONTROL, "ctrl"),
+            (KeyModifiers::ALT, "alt"),
+            (KeyModifiers::SHIFT, "shift"),
+        ] {
+            if self.modifiers.contains(modifier) {
+                write!(f, "{name}+")?;
+            }
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "space"),
+            KeyCode::Char(c) => write!(f, "{c}"),
+            KeyCode::F(n) => write!(f, "f{n}"),
+            code => write!(f, "{}", format!("{code:?}").to_lowercase()),
+        }
+    }
+}
+
+impl FromStr for KeyBinding {
+    type Err = String;
+    /// Parses keys like `q`, `space`, `ctrl+c`, `shift+up` or `ctrl++`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let error = || format!("Invalid key: {value}");
+        let (modifiers, key) = match value.strip_suffix('+') {
+            Some("") => ("", "+"),
+            Some(rest) if rest.ends_with('+') => (&rest[..rest.len() - 1], "+"),
+            _ => value.rsplit_once('+').unwrap_or(("", value)),
+        };
+
+        let mut binding = Self {
+            code: KeyCode::Null,
+            modifiers: KeyModifiers::NONE,
+        };
+        for modifier in modifiers.split('+').filter(|m| !m.is_empty()) {
+            binding.modifiers |= match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" | "meta" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return Err(error()),
+            };
+        }
+
+        let mut chars = key.chars();
+        binding.code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match key.to_lowercase().as_str() {
+                "space" => KeyCode::Char(' '),
+                "esc" | "escape" => KeyCode::Esc,
+                "enter" | "return" => KeyCode::Enter,
+                "tab" => KeyCode::Tab,
+                "backspace" => KeyCode::Backspace,
+                "delete" | "del" => KeyCode::Delete,
+                "insert" => KeyCode::Insert,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                name => match name.strip_prefix('f').map(u8::from_str) {
+                    Some(Ok(n @ 1..=12)) => KeyCode::F(n),
+                    _ => return Err(error()),
+                },
+            },
+        };
+        Ok(binding)
+    }
+}
+
+/// One key or a list of keys for an action in the `[keys]` table of the config.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+pub enum Keys {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Keys {
+    fn iter(&self) -> impl Iterator<Item = &str> {
+        match self {
+            Keys::One(key) => std::slice::from_ref(key).iter(),
+            Keys::Many(keys) => keys.iter(),
+        }
+        .map(String::as_str)
+    }
+}
+
+/// Keys bound to each action.
+#[derive(Debug, Clone)]
+pub struct KeyMap {
+    bindings: Vec<(KeyBinding, Action)>,
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        let defaults = [
+            (Action::Quit, &["esc", "ctrl+c", "q", "Q"][..]),
+            (Action::Suspend, &["ctrl+z"]),
+            (Action::Pause, &["space"]),
+            (Action::Step, &["."]),
+            (Action::SpeedUp, &["+", "="]),
+            (Action::SlowDown, &["-"]),
+            (Action::Up, &["up"]),
+            (Action::Down, &["down"]),
+            (Action::Left, &["left"]),
+            (Action::Right, &["right"]),
+            (Action::NextGroup, &["g"]),
+            (Action::NextColor, &["c"]),
+            (Action::ToggleShade, &["s"]),
+            (Action::ToggleHud, &["?", "h"]),
+        ];
+        let bindings = defaults
+            .iter()
+            .flat_map(|(action, keys)| {
+                keys.iter().map(|key| {
+                    let binding = KeyBinding::from_str(key).expect("default keys are valid");
+                    (binding, *action)
+                })
+            })
+            .collect();
+        Self { bindings }
+    }
+}
+
+impl KeyMap {
+    pub fn action(&self, key: &KeyEvent) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(binding, _)| binding.matches(key))
+            .map(|(_, action)| *action)
+    }
+
+    /// Keys bound to `action`, in the order they were bound.
+    pub fn keys(&self, action: Action) -> impl Iterator<Item = KeyBinding> + '_ {
+        self.bindings
+            .iter()
+            .filter(move |(_, bound)| *bound == action)
+            .map(|(binding, _)| *binding)
+    }
+
+    /// Binds `keys` to `action` in place of its default keys, taking them away from any other
+    /// action they were bound to. An empty list leaves the action without a key.
+    pub fn bind<'a>(
+        &mut self,
+        action: Action,
+        keys: impl IntoIterator<Item = &'a str>,
+    ) -> Result<(), String> {
+        let keys = keys
+            .into_iter()
+            .map(KeyBinding::from_str)
+            .collect::<Result<Vec<_>, _>>()?;
+        self.bindings
+            .retain(|(binding, bound)| *bound != action && !keys.contains(binding));
+        self.bindings
+            .extend(keys.into_iter().map(|key| (key, action)));
+        Ok(())
+    }
+
+    /// Applies the `[keys]` table of the config, printing the entries that can't be used.
+    pub fn configure(&mut self, keys: &std::collections::BTreeMap<String, Keys>) {
+        for (action, keys) in keys {
+            let result = Action::from_str(action).and_then(|action| self.bind(action, keys.iter()));
+            if let Err(err) = result {
+                eprintln!("keys.{action} {err}");
+            }
+        }
+    }
+}
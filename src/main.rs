@@ -1,11 +1,22 @@
 mod cli;
+mod countdown;
+mod font;
+mod hud;
+mod image;
+mod intro;
+mod keys;
+mod mask;
+mod message;
+mod render;
+mod source;
 #[cfg(test)]
 mod test;
+mod wall_clock;
 
 use clap::Parser;
 use crossterm::{
-    cursor, event, execute, queue,
-    style::{Color, Print, SetBackgroundColor, SetForegroundColor},
+    cursor, event, execute,
+    style::{Color, Print, ResetColor, SetBackgroundColor},
     terminal::{self, Clear, ClearType},
 };
 
@@ -16,16 +27,37 @@ use rand::RngExt;
 use rand::SeedableRng;
 
 use std::{
-    io::{BufWriter, Stdout, Write, stdout},
+    io::{BufReader, BufWriter, IsTerminal, Stdout, stdout},
     str::FromStr,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
     time::{Duration, Instant},
 };
 
-use crate::cli::Grouping;
+use crate::{
+    cli::Grouping,
+    countdown::Countdown,
+    hud::Hud,
+    image::Picture,
+    intro::Intro,
+    keys::Action,
+    mask::Mask,
+    message::Message,
+    render::{FrameStats, RenderThread, Renderer},
+    source::Source,
+    wall_clock::WallClock,
+};
+
+const DEFAULT_SPEED: &str = "5..20";
+/// Longest the main loop sleeps waiting for input when no column is scheduled to move.
+const IDLE_POLL_INTERVAL: Duration = Duration::from_secs(1);
 
-const MAXSPEED: u64 = 0;
-const MINSPEED: u64 = 200;
-const POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Set while the terminal is in raw mode on the alternate screen, so it is only restored once.
+static TERMINAL_ACTIVE: AtomicBool = AtomicBool::new(false);
+/// Set while mouse, focus and paste events are captured for the screensaver.
+static INPUT_CAPTURE: AtomicBool = AtomicBool::new(false);
 
 const AUTHOR: &str = "
 ▞▀▖       ▌        ▞▀▖▞▀▖▞▀▖▛▀▘
@@ -82,6 +114,65 @@ impl Clock for SystemClock {
     }
 }
 
+/// Clock that can be stopped. Time stands still for the rain while it is paused and carries on
+/// from the same moment when it is resumed.
+#[derive(Debug)]
+struct PausableClock {
+    inner: Box<dyn Clock>,
+    /// Time on `inner` and on this clock when it was last started, they drift apart with every pause
+    started: (Instant, Instant),
+    /// Time the clock stopped at
+    paused_at: Option<Instant>,
+}
+
+impl PausableClock {
+    fn new(inner: Box<dyn Clock>) -> Self {
+        let now = inner.now();
+        Self {
+            inner,
+            started: (now, now),
+            paused_at: None,
+        }
+    }
+
+    fn pause(&mut self) {
+        if self.paused_at.is_none() {
+            self.paused_at = Some(self.now());
+        }
+    }
+
+    fn resume(&mut self) {
+        if let Some(at) = self.paused_at.take() {
+            self.started = (self.inner.now(), at);
+        }
+    }
+
+    fn is_paused(&self) -> bool {
+        self.paused_at.is_some()
+    }
+
+    /// Moves a paused clock forward to `at`.
+    fn skip_to(&mut self, at: Instant) {
+        if let Some(paused_at) = &mut self.paused_at {
+            *paused_at = (*paused_at).max(at);
+        }
+    }
+}
+
+impl Clock for PausableClock {
+    fn now(&self) -> Instant {
+        self.paused_at.unwrap_or_else(|| {
+            let (inner, started) = self.started;
+            started + self.inner.now().saturating_duration_since(inner)
+        })
+    }
+
+    #[cfg(test)]
+    fn advance(&mut self, duration: Duration) {
+        self.inner.advance(duration);
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 struct Cell {
     char: char,
@@ -104,18 +195,14 @@ impl Cell {
     fn is_visible(&self) -> bool {
         self.char != ' '
     }
+}
 
-    fn display(&self, width: usize) -> String {
-        let c = if width >= 2 && !self.is_visible() {
-            " ".repeat(width)
-        } else {
-            self.char.to_string()
-        };
-        if cfg!(test) {
-            c
-        } else {
-            format!("{}{}", crossterm::style::SetForegroundColor(self.color), c)
-        }
+/// Cells of the wide groups take up two columns, so text laid out on the grid swaps letters for
+/// their full width forms to keep the rest of the row lined up.
+fn fit_width(c: char, char_width: usize) -> char {
+    match c {
+        '!'..='~' if char_width == 2 => char::from_u32(c as u32 + 0xFEE0).unwrap_or(c),
+        c => c,
     }
 }
 
@@ -153,12 +240,349 @@ impl FromStr for Direction {
     }
 }
 
+/// Length of a rain trail, either an absolute number of cells or a fraction of the screen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrailLength {
+    Cells(usize),
+    Fraction(f32),
+}
+
+impl TrailLength {
+    /// Resolves the length in cells for a screen that is `screen` cells long in the direction
+    /// the rain is falling.
+    fn cells(&self, screen: usize) -> usize {
+        match self {
+            Self::Cells(cells) => *cells,
+            Self::Fraction(fraction) => (screen as f32 * fraction).round() as usize,
+        }
+    }
+}
+
+impl FromStr for TrailLength {
+    type Err = String;
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        if let Some(percent) = value.strip_suffix('%') {
+            return match percent.trim().parse::<f32>() {
+                Ok(p) if (0.0..=100.0).contains(&p) => Ok(Self::Fraction(p / 100.0)),
+                _ => Err(format!(
+                    "Invalid trail length: {value} (expected a percentage between 0% and 100%)"
+                )),
+            };
+        }
+        if let Ok(cells) = value.parse::<usize>() {
+            return Ok(Self::Cells(cells));
+        }
+        match value.parse::<f32>() {
+            Ok(fraction) if fraction.is_finite() && (0.0..=1.0).contains(&fraction) => {
+                Ok(Self::Fraction(fraction))
+            }
+            _ => Err(format!(
+                "Invalid trail length: {value} (expected a number of cells, a fraction between 0 and 1 or a percentage)"
+            )),
+        }
+    }
+}
+
+/// How trail lengths are picked between the min and max length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Distribution {
+    #[default]
+    Uniform,
+    Normal,
+    Exponential,
+}
+
+impl Distribution {
+    /// Picks a length in `min..=max`.
+    fn sample(&self, rng: &mut Random, min: usize, max: usize) -> usize {
+        if min >= max {
+            return min;
+        }
+        let spread = (max - min) as f32;
+        let offset = match self {
+            Self::Uniform => return rng.random_range(min..=max),
+            // Box-Muller transform, most of the trails end up around the middle of the range.
+            Self::Normal => {
+                let u1: f32 = rng.random_range(f32::EPSILON..1.0);
+                let u2: f32 = rng.random_range(0.0..1.0);
+                let z = (-2.0 * u1.ln()).sqrt() * (std::f32::consts::TAU * u2).cos();
+                spread / 2.0 + z * spread / 6.0
+            }
+            // Lots of short trails with the occasional long one.
+            Self::Exponential => {
+                let u: f32 = rng.random_range(f32::EPSILON..1.0);
+                -u.ln() * spread / 4.0
+            }
+        };
+        min + (offset.round().max(0.0) as usize).min(max - min)
+    }
+}
+
+impl FromStr for Distribution {
+    type Err = String;
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "uniform" => Ok(Self::Uniform),
+            "normal" | "gaussian" => Ok(Self::Normal),
+            "exponential" | "exp" => Ok(Self::Exponential),
+            _ => Err(format!("Invalid distribution: {value}")),
+        }
+    }
+}
+
+/// Range of speeds in cells per second that drops are spawned with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Speed {
+    min: f32,
+    max: f32,
+}
+
+impl Speed {
+    /// Slowest and fastest speed that can be set, in cells per second
+    const LIMITS: std::ops::RangeInclusive<f32> = 0.01..=1000.0;
+
+    fn scaled(&self, factor: f32) -> Self {
+        Self {
+            min: self.min * factor,
+            max: self.max * factor,
+        }
+    }
+
+    /// Picks the interval between two steps of a new drop.
+    fn sample_interval(&self, rng: &mut Random) -> Duration {
+        let cells_per_second = if self.min < self.max {
+            rng.random_range(self.min..=self.max)
+        } else {
+            self.min
+        };
+        Duration::from_secs_f64(1.0 / cells_per_second as f64)
+    }
+}
+
+impl FromStr for Speed {
+    type Err = String;
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let error = || {
+            let (slowest, fastest) = Self::LIMITS.into_inner();
+            format!(
+                "Invalid speed: {value} (expected cells per second from {slowest} to {fastest}, \
+                 e.g. 20 or 5..30)"
+            )
+        };
+        let parse = |s: &str| match s.trim().parse::<f32>() {
+            Ok(speed) if Self::LIMITS.contains(&speed) => Ok(speed),
+            _ => Err(error()),
+        };
+        let (min, max) = match value.split_once("..") {
+            Some((min, max)) => (parse(min)?, parse(max.trim_start_matches('='))?),
+            None => {
+                let speed = parse(value)?;
+                (speed, speed)
+            }
+        };
+        if min > max {
+            return Err(format!(
+                "Invalid speed: {value} (the first number has to be the slowest speed)"
+            ));
+        }
+        Ok(Self { min, max })
+    }
+}
+
+/// Shape of a drop's speed over its lifetime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpeedCurve {
+    /// Same speed from start to finish
+    #[default]
+    Constant,
+    /// Starts slow and accelerates as it falls
+    Gravity,
+    /// Starts fast and slows down as it falls
+    Brake,
+    /// Speeds up through the middle of the screen and slows down at the end
+    Swell,
+}
+
+impl SpeedCurve {
+    /// Multiplier applied to a drop's base interval at `progress` (0.0 to 1.0) through its life.
+    /// `strength` is how many times slower the drop is at the slowest point of the curve.
+    fn scale(&self, progress: f32, strength: f32) -> f32 {
+        let progress = progress.clamp(0.0, 1.0);
+        let slowdown = match self {
+            Self::Constant => 0.0,
+            Self::Gravity => (1.0 - progress).powi(2),
+            Self::Brake => progress.powi(2),
+            Self::Swell => 1.0 - (std::f32::consts::PI * progress).sin(),
+        };
+        1.0 + (strength - 1.0).max(0.0) * slowdown
+    }
+}
+
+impl FromStr for SpeedCurve {
+    type Err = String;
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "constant" => Ok(Self::Constant),
+            "gravity" | "accelerate" => Ok(Self::Gravity),
+            "brake" | "decelerate" => Ok(Self::Brake),
+            "swell" => Ok(Self::Swell),
+            _ => Err(format!("Invalid speed curve: {value}")),
+        }
+    }
+}
+
+/// Animation timing of a single column.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Timing {
+    /// When the column last moved, or when it will start moving for the first time
+    last: Instant,
+    /// Interval the drop was spawned with
+    base: Duration,
+    /// Interval until the next step, this is `base` shaped by the speed curve
+    interval: Duration,
+}
+
+impl Timing {
+    fn new(last: Instant, base: Duration) -> Self {
+        Self {
+            last,
+            base,
+            interval: base,
+        }
+    }
+
+    #[inline(always)]
+    fn next_due(&self) -> Instant {
+        self.last + self.interval
+    }
+}
+
+/// Hashed timer wheel that buckets columns by the millisecond they are next due to move.
+/// Finding the columns that have to move costs about as much as the number of columns that move,
+/// instead of the number of columns on screen.
+#[derive(Debug)]
+struct TimerWheel {
+    /// Time of tick zero
+    epoch: Instant,
+    /// First entry of each slot. Entries land in slot `tick % SLOTS`, so anything due more than a
+    /// lap of the wheel ahead shares a slot with earlier entries and waits there for its lap.
+    heads: Vec<u32>,
+    /// Entries of every slot, linked together within their slot.
+    /// Kept in one list so the wheel stops allocating once it has seen the most entries it needs.
+    entries: Vec<WheelEntry>,
+    /// First unused entry in `entries`
+    free: u32,
+    /// Oldest tick that may still have entries waiting
+    cursor: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct WheelEntry {
+    due: Instant,
+    column: usize,
+    /// Next entry in the same slot, or in the free list
+    next: u32,
+}
+
+impl TimerWheel {
+    const SLOTS: usize = 1024;
+    const NONE: u32 = u32::MAX;
+
+    fn new(epoch: Instant, capacity: usize) -> Self {
+        Self {
+            epoch,
+            heads: vec![Self::NONE; Self::SLOTS],
+            entries: Vec::with_capacity(capacity),
+            free: Self::NONE,
+            cursor: 0,
+        }
+    }
+
+    #[inline(always)]
+    fn tick(&self, at: Instant) -> u64 {
+        let since = at.saturating_duration_since(self.epoch);
+        since.as_secs() * 1000 + since.subsec_millis() as u64
+    }
+
+    #[inline(always)]
+    fn insert(&mut self, due: Instant, column: usize) {
+        let slot = self.tick(due).max(self.cursor) as usize % Self::SLOTS;
+        let entry = WheelEntry {
+            due,
+            column,
+            next: self.heads[slot],
+        };
+        let index = if self.free == Self::NONE {
+            self.entries.push(entry);
+            self.entries.len() as u32 - 1
+        } else {
+            let index = self.free;
+            self.free = self.entries[index as usize].next;
+            self.entries[index as usize] = entry;
+            index
+        };
+        self.heads[slot] = index;
+    }
+
+    /// Moves every entry due in a tick up to and including the tick of `now` into `expired`.
+    /// Entries are handed out by tick, so some may be due later within the tick of `now`.
+    fn expire(&mut self, now: Instant, expired: &mut Vec<(Instant, usize)>) {
+        let now_tick = self.tick(now);
+        // After a long sleep a single lap of the wheel already covers every slot.
+        let end = now_tick.min(self.cursor + Self::SLOTS as u64 - 1);
+        for tick in self.cursor..=end {
+            let slot = tick as usize % Self::SLOTS;
+            let mut prev = Self::NONE;
+            let mut index = self.heads[slot];
+            while index != Self::NONE {
+                let entry = self.entries[index as usize];
+                if self.tick(entry.due) > now_tick {
+                    prev = index;
+                    index = entry.next;
+                    continue;
+                }
+                expired.push((entry.due, entry.column));
+                if prev == Self::NONE {
+                    self.heads[slot] = entry.next;
+                } else {
+                    self.entries[prev as usize].next = entry.next;
+                }
+                self.entries[index as usize].next = self.free;
+                self.free = index;
+                index = entry.next;
+            }
+        }
+        self.cursor = self.cursor.max(now_tick);
+    }
+
+    /// Earliest time an entry within the next lap of the wheel can be due.
+    /// Stale entries are counted too, so this may be earlier than any column really is.
+    fn next_due(&self) -> Option<Instant> {
+        (self.cursor..self.cursor + Self::SLOTS as u64)
+            .find(|tick| {
+                let mut index = self.heads[*tick as usize % Self::SLOTS];
+                while index != Self::NONE {
+                    let entry = &self.entries[index as usize];
+                    if self.tick(entry.due) <= *tick {
+                        return true;
+                    }
+                    index = entry.next;
+                }
+                false
+            })
+            .map(|tick| self.epoch + Duration::from_millis(tick + 1))
+    }
+}
+
 #[derive(Debug)]
 struct Rain<const LENGTH: usize> {
     /// Random number generator wrapper for testing purposes
     rng: Random,
     /// A configurable Clock interface to help control time while in a test env
-    clock: Box<dyn Clock>,
+    clock: PausableClock,
     /// Characters to use for the rain
     chars: [char; LENGTH],
     /// Starting positions of the rain within the chars array
@@ -167,6 +591,20 @@ struct Rain<const LENGTH: usize> {
     starts: Vec<usize>,
     /// Window size for each column of rain
     windows: Vec<usize>,
+    /// Shortest trail length in cells
+    min_length: usize,
+    /// Longest trail length in cells
+    max_length: usize,
+    /// How trail lengths are picked between `min_length` and `max_length`
+    distribution: Distribution,
+    /// Columns that are currently allowed to rain
+    active: Vec<bool>,
+    /// Columns waiting for an active column to finish before they can start raining.
+    /// Only used when the spawn density is below 1.0
+    idle: Vec<usize>,
+    /// Column (or row for sideways rain) each stream of rain falls in.
+    /// Streams are spread out by the column gap and sparse columns are left out.
+    lanes: Vec<usize>,
     /// Current positions of the rain falling
     positions: Vec<usize>,
     /// Color of the rain body
@@ -182,11 +620,25 @@ struct Rain<const LENGTH: usize> {
     /// Direction of the rain
     directions: Vec<Direction>,
     /// Animation timing
-    time: Vec<(Instant, Duration)>,
+    time: Vec<Timing>,
+    /// Columns bucketed by when they are next due to move.
+    /// Entries are never removed when a timing changes, instead an entry is stale when it no longer
+    /// matches `time` and gets dropped once it expires.
+    schedule: TimerWheel,
+    /// Entries taken out of `schedule` this frame, kept around to reuse the allocation
+    expired: Vec<(Instant, usize)>,
+    /// How the speed of a drop changes over its life
+    speed_curve: SpeedCurve,
+    /// How many times slower a drop is at the slowest point of `speed_curve`
+    curve_strength: f32,
     /// List of columns that need to be updated
     queue: Vec<usize>,
-    /// Speed of the rain
-    speed: std::ops::Range<u64>,
+    /// Speed of rain falling up or down
+    vertical_speed: Speed,
+    /// Speed of rain moving left or right
+    horizontal_speed: Speed,
+    /// How many times faster than the configured speed the rain is running
+    speed_scale: f32,
     /// Character width
     char_width: usize,
     /// Width of the terminal
@@ -194,25 +646,42 @@ struct Rain<const LENGTH: usize> {
     /// 🌕 is a single character but takes up 2 columns and so the width value would count this as
     /// 1.  This maybe be a wrong way to think about it 🤷 (two wide character).
     width: usize,
+    /// True width of the terminal in columns
+    columns: usize,
     /// Height of the terminal.
     /// Unlike width this is a true height of the terminal
     height: usize,
     /// Current screen buffer
     screen_buffer: Vec<Cell>,
-    /// Previous screen buffer
-    previous_screen_buffer: Vec<Cell>,
+    /// Set once the rain is winding down, finished drops don't start again
+    draining: bool,
+    /// Text the drops reveal as they fall through it
+    message: Option<Message>,
+    /// Shape the rain is kept to
+    mask: Option<Mask>,
+    /// Picture that sets the color of the rain cell by cell
+    picture: Option<Picture>,
+    /// Text the characters come from instead of the group
+    source: Option<Source>,
+    /// Part of the text from `source` each stream spells out along its way across the screen
+    spelled: Vec<Vec<char>>,
+    /// Set while `redraw` draws the streams where they were, a stream back at the top of the
+    /// screen keeps the text it was spelling instead of taking more
+    redrawing: bool,
 }
 
 impl<const LENGTH: usize> Rain<LENGTH> {
-    const MIN_LENGTH_OF_RAIN: usize = 4;
     const MAX_LENGTH_OFFSET_OF_RAIN: usize = 4;
+    /// Slowest and fastest the speed can be changed to, relative to the configured speed
+    const SPEED_SCALE: std::ops::RangeInclusive<f32> = 1.0 / 16.0..=16.0;
+
     fn new(
-        mut width: usize,
+        columns: usize,
         height: usize,
         settings: &cli::Cli,
         clock: impl Clock + 'static,
     ) -> Self {
-        width /= settings.group.width() as usize;
+        let width = columns / settings.group.width() as usize;
 
         let mut rng = Random::default();
         let char_length = settings.group.len();
@@ -223,93 +692,461 @@ impl<const LENGTH: usize> Rain<LENGTH> {
                 .unwrap_or('#') // fallback character
         });
 
-        let starts: Vec<usize> = (0..width)
+        let (window_height, cross) = match settings.direction {
+            Direction::Up | Direction::Down => (height, width),
+            Direction::Left | Direction::Right => (width, height),
+        };
+        let lanes = Self::lanes(cross, settings, &mut rng);
+        let streams = lanes.len();
+
+        let starts: Vec<usize> = (0..streams)
             .map(|_| rng.random_range(0..chars.len()))
             .collect();
 
-        let window_height = match settings.direction {
-            Direction::Up | Direction::Down => height,
-            Direction::Left | Direction::Right => width,
-        };
-
-        let windows: Vec<usize> = (0..width)
+        let (min_length, max_length) = Self::length_bounds(settings, window_height);
+        let windows: Vec<usize> = (0..streams)
             .map(|_| {
-                rng.random_range(
-                    Self::MIN_LENGTH_OF_RAIN
-                        ..window_height.saturating_sub(Self::MAX_LENGTH_OFFSET_OF_RAIN),
-                )
+                settings
+                    .length_distribution
+                    .sample(&mut rng, min_length, max_length)
             })
             .collect();
 
-        let speed = settings.speed_range();
+        let mut active = vec![true; streams];
+        let mut idle = Vec::with_capacity(streams);
+        if settings.density < 1.0 {
+            active.fill(false);
+            idle.extend(0..streams);
+            let count = (streams as f32 * settings.density).round() as usize;
+            for _ in 0..count.min(streams) {
+                let i = idle.swap_remove(rng.random_range(0..idle.len()));
+                active[i] = true;
+            }
+        }
+
+        let speed = settings.speed_for(settings.direction);
         let now = clock.now();
-        let time: Vec<(Instant, Duration)> = (0..width)
+        let time: Vec<Timing> = (0..streams)
             .map(|_| {
-                let milli_seconds = rng.random_range(speed.start..speed.end);
-                let duration = Duration::from_millis(milli_seconds);
+                let duration = speed.sample_interval(&mut rng);
                 let future_delay_ms = rng.random_range(0..2000);
                 let start = now + Duration::from_millis(future_delay_ms);
 
-                (start, duration)
+                Timing::new(start, duration)
             })
             .collect();
 
+        let mut schedule = TimerWheel::new(now, streams * 2);
+        for (i, timing) in time.iter().enumerate() {
+            schedule.insert(timing.next_due(), i);
+        }
+
         let shade_color: Color = settings.shade_gradient_color().into();
         let body_colors = if settings.shade {
             let base_color: Color = settings.rain_color().into();
-            (0..width)
+            (0..streams)
                 .map(|i| {
                     let window = windows[i].saturating_sub(1);
-                    let colors = gen_shade_color(base_color, shade_color, window as u8);
+                    let mut colors = gen_shade_color(base_color, shade_color, window);
+                    // Room for the longest trail so a reset never has to grow it.
+                    colors.reserve(max_length.saturating_sub(colors.len()));
                     (base_color, Some(colors))
                 })
                 .collect::<Vec<_>>()
         } else {
-            vec![(settings.rain_color().into(), None); width]
+            vec![(settings.rain_color().into(), None); streams]
         };
 
+        let message = settings.message.as_deref().map(|text| {
+            let mut message = Message::new(text, settings.message_color().into());
+            message.layout(width, height, settings.group.width() as usize);
+            message
+        });
+
+        let mask = settings.mask.as_deref().map(|text| {
+            let mut mask = Mask::from_text(text, settings.invert_mask);
+            mask.layout(width, height, settings.group.width() as usize);
+            mask
+        });
+
         Self {
-            clock: Box::new(clock),
+            active,
+            clock: PausableClock::new(Box::new(clock)),
             shading: settings.shade,
             shade_gradient: shade_color,
             body_colors,
             chars,
-            directions: vec![settings.direction; width],
+            curve_strength: settings.curve_strength,
+            directions: vec![settings.direction; streams],
+            expired: Vec::with_capacity(streams),
             char_width: settings.group.width() as usize,
-            head_colors: vec![settings.head_color().into(); width],
+            columns,
+            distribution: settings.length_distribution,
+            head_colors: vec![settings.head_color().into(); streams],
             height,
-            positions: vec![0; width],
-            previous_screen_buffer: vec![Cell::default(); width * height],
-            queue: Vec::with_capacity(width),
+            horizontal_speed: settings.speed_for(Direction::Right),
+            idle,
+            max_length,
+            min_length,
+            lanes,
+            positions: vec![0; streams],
+            queue: Vec::with_capacity(streams),
             rng,
+            schedule,
             screen_buffer: vec![Cell::default(); width * height],
-            speed,
+            speed_curve: settings.speed_curve,
+            speed_scale: 1.0,
             starts,
             time,
+            vertical_speed: settings.speed_for(Direction::Down),
             width,
             windows,
+            draining: false,
+            message,
+            mask,
+            picture: None,
+            source: None,
+            spelled: vec![Vec::new(); streams],
+            redrawing: false,
+        }
+    }
+
+    /// Picks the column (or row for sideways rain) of each stream out of the `cross` available.
+    fn lanes(cross: usize, settings: &cli::Cli, rng: &mut Random) -> Vec<usize> {
+        (0..cross)
+            .step_by(settings.column_gap + 1)
+            .filter(|_| {
+                settings
+                    .sparse
+                    .is_none_or(|p| rng.random_range(0.0..1.0) >= p)
+            })
+            .collect()
+    }
+
+    /// Lays the streams out again after the screen or the direction changed.
+    /// Streams that still fit keep falling where they were and new ones start where there is room
+    /// for more, the screen is cleared and filled in again as the streams move.
+    fn relayout(&mut self, settings: &cli::Cli) {
+        let direction = settings.direction;
+        let (window_height, cross) = match direction {
+            Direction::Up | Direction::Down => (self.height, self.width),
+            Direction::Left | Direction::Right => (self.width, self.height),
+        };
+        self.lanes = Self::lanes(cross, settings, &mut self.rng);
+        let streams = self.lanes.len();
+        (self.min_length, self.max_length) = Self::length_bounds(settings, window_height);
+
+        let kept = self.positions.len().min(streams);
+        self.starts.truncate(kept);
+        self.windows.truncate(kept);
+        self.positions.truncate(kept);
+        self.active.truncate(kept);
+        self.body_colors.truncate(kept);
+        self.head_colors.truncate(kept);
+        self.directions.truncate(kept);
+        self.time.truncate(kept);
+        self.spelled.truncate(kept);
+        self.idle.retain(|&i| i < kept);
+        self.queue.clear();
+
+        let now = self.clock.now();
+        for i in kept..streams {
+            self.starts.push(0);
+            self.windows.push(0);
+            self.positions.push(0);
+            self.active.push(false);
+            self.body_colors.push((Color::Reset, None));
+            self.head_colors.push(Color::Reset);
+            self.directions.push(direction);
+            self.time.push(Timing::new(now, Duration::ZERO));
+            self.spelled.push(Vec::new());
+            self.reset_start(i);
+            self.reset_window(i);
+            self.idle.push(i);
+        }
+        self.set_colors(settings);
+
+        self.schedule = TimerWheel::new(now, streams * 2);
+        for i in 0..kept {
+            if self.active[i] {
+                self.schedule(i);
+            }
+        }
+
+        // Keep the same share of streams raining as before.
+        let target = if settings.density < 1.0 {
+            (streams as f32 * settings.density).round() as usize
+        } else {
+            streams
+        };
+        let mut count = self.active.iter().filter(|active| **active).count();
+        for i in (0..kept).rev() {
+            if count <= target {
+                break;
+            }
+            if self.active[i] {
+                self.active[i] = false;
+                self.idle.push(i);
+                count -= 1;
+            }
+        }
+        // Rain on its way out doesn't start any new streams.
+        while count < target && !self.idle.is_empty() && !self.draining {
+            let next = self
+                .idle
+                .swap_remove(self.rng.random_range(0..self.idle.len()));
+            self.active[next] = true;
+            self.reset_time(next);
+            count += 1;
+        }
+
+        self.screen_buffer.clear();
+        self.screen_buffer
+            .resize(self.width * self.height, Cell::default());
+        if let Some(message) = &mut self.message {
+            message.layout(self.width, self.height, self.char_width);
+            message.draw(&mut self.screen_buffer);
+        }
+        if let Some(mask) = &mut self.mask {
+            mask.layout(self.width, self.height, self.char_width);
+        }
+        if let Some(picture) = &mut self.picture {
+            picture.layout(self.width, self.height);
         }
     }
 
+    /// Fits the rain to a terminal of `columns` by `height` without starting over.
+    fn resize(&mut self, columns: usize, height: usize, settings: &cli::Cli) {
+        self.columns = columns;
+        self.width = columns / self.char_width;
+        self.height = height;
+        self.relayout(settings);
+    }
+
+    /// Turns the rain to `settings.direction`.
+    fn set_direction(&mut self, settings: &cli::Cli) {
+        self.directions.fill(settings.direction);
+        self.relayout(settings);
+    }
+
+    /// Switches to the characters of `settings.group`. With a source the text stays and only the
+    /// width of the characters changes.
+    fn set_group(&mut self, settings: &cli::Cli) {
+        let char_length = settings.group.len();
+        for c in self.chars.iter_mut().filter(|_| self.source.is_none()) {
+            *c = settings
+                .group
+                .nth_char(self.rng.random_range(0..char_length))
+                .unwrap_or('#');
+        }
+
+        let char_width = settings.group.width() as usize;
+        if char_width != self.char_width {
+            self.char_width = char_width;
+            self.width = self.columns / char_width;
+            self.relayout(settings);
+        }
+    }
+
+    /// Picks up the colors and shading in `settings` for every stream.
+    fn set_colors(&mut self, settings: &cli::Cli) {
+        self.shading = settings.shade;
+        self.shade_gradient = settings.shade_gradient_color().into();
+        self.head_colors.fill(settings.head_color().into());
+        let base_color: Color = settings.rain_color().into();
+        for i in 0..self.body_colors.len() {
+            let shades = self.shading.then(|| Vec::with_capacity(self.max_length));
+            self.body_colors[i] = (base_color, shades);
+            self.reset_body_colors(i);
+        }
+    }
+
+    /// Makes the rain `factor` times faster, drops that are already falling included.
+    fn change_speed(&mut self, factor: f32) {
+        let scale =
+            (self.speed_scale * factor).clamp(*Self::SPEED_SCALE.start(), *Self::SPEED_SCALE.end());
+        let factor = scale / self.speed_scale;
+        self.speed_scale = scale;
+
+        self.vertical_speed = self.vertical_speed.scaled(factor);
+        self.horizontal_speed = self.horizontal_speed.scaled(factor);
+        for i in 0..self.time.len() {
+            let timing = &mut self.time[i];
+            timing.base = timing.base.div_f32(factor);
+            timing.interval = timing.interval.div_f32(factor);
+            if self.active[i] {
+                self.schedule(i);
+            }
+        }
+    }
+
+    fn is_paused(&self) -> bool {
+        self.clock.is_paused()
+    }
+
+    /// Stops new drops from starting. Drops that are already falling carry on until they are off
+    /// the screen.
+    fn drain(&mut self) {
+        self.draining = true;
+        self.idle.clear();
+        for i in 0..self.active.len() {
+            if self.positions[i] == 0 {
+                self.active[i] = false;
+            }
+        }
+    }
+
+    /// Whether the rain has drained and the screen is empty.
+    fn is_drained(&self) -> bool {
+        self.draining && !self.active.contains(&true)
+    }
+
+    fn toggle_pause(&mut self) {
+        if self.clock.is_paused() {
+            self.clock.resume();
+        } else {
+            self.clock.pause();
+        }
+    }
+
+    /// Moves paused rain forward to the next time a column is due and updates it, so the next
+    /// `update_screen_buffer` draws a single step.
+    fn step(&mut self) {
+        while self.queue.is_empty() {
+            let Some(due) = self.next_due() else {
+                return;
+            };
+            self.clock.skip_to(due);
+            self.update();
+        }
+    }
+
+    /// Resolves the min and max trail length for a screen that is `window_height` cells long in
+    /// the direction of the rain.
+    /// Falls back to something that fits when the screen is too small for the configured range.
+    fn length_bounds(settings: &cli::Cli, window_height: usize) -> (usize, usize) {
+        let max = match settings.max_length {
+            Some(length) => length.cells(window_height),
+            None => window_height.saturating_sub(Self::MAX_LENGTH_OFFSET_OF_RAIN + 1),
+        };
+        let min = settings
+            .min_length
+            .cells(window_height)
+            .clamp(1, window_height.max(1));
+        (min, max.max(min))
+    }
+
     #[inline(always)]
     fn update(&mut self) {
         let now = self.clock.now();
-        for i in 0..self.width {
-            let (start, duration) = self.time[i];
-            if now.duration_since(start) > duration {
+        let mut expired = std::mem::take(&mut self.expired);
+        self.schedule.expire(now, &mut expired);
+        for (due, i) in expired.drain(..) {
+            if !self.is_scheduled(due, i) {
+                continue;
+            }
+            if due > now {
+                self.schedule.insert(due, i);
+            } else {
                 self.queue.push(i);
-                let (start, _) = &mut self.time[i];
-                *start = now;
             }
         }
+        self.expired = expired;
+        // The wheel hands out a slot in the order columns went into it, sorting keeps a run from
+        // depending on that. A column can be in it more than once after a speed change.
+        self.queue.sort_unstable();
+        self.queue.dedup();
+
+        for q in 0..self.queue.len() {
+            let i = self.queue[q];
+            let interval = self.step_interval(i);
+            let timing = &mut self.time[i];
+            timing.last = now;
+            timing.interval = interval;
+            self.schedule(i);
+        }
+    }
+
+    /// Whether a `schedule` entry is still the current one for column `i`.
+    #[inline(always)]
+    fn is_scheduled(&self, due: Instant, i: usize) -> bool {
+        self.active[i] && self.time[i].next_due() == due
+    }
+
+    #[inline(always)]
+    fn schedule(&mut self, i: usize) {
+        self.schedule.insert(self.time[i].next_due(), i);
+    }
+
+    /// When the next column is scheduled to move.
+    /// May be a little early, but never later than the column is due.
+    fn next_due(&self) -> Option<Instant> {
+        self.schedule.next_due()
+    }
+
+    /// Interval between the step that is about to be drawn for column `i` and the one after.
+    #[inline(always)]
+    fn step_interval(&self, i: usize) -> Duration {
+        let travel = match self.directions[i] {
+            Direction::Up | Direction::Down => self.height,
+            Direction::Left | Direction::Right => self.width,
+        } + self.windows[i];
+        let progress = (self.positions[i] + 1) as f32 / travel.max(1) as f32;
+        self.shape_interval(self.time[i].base, progress)
+    }
+
+    /// Applies the speed curve to `base` at `progress` through a drop's life.
+    #[inline(always)]
+    fn shape_interval(&self, base: Duration, progress: f32) -> Duration {
+        if self.speed_curve == SpeedCurve::Constant {
+            return base;
+        }
+        base.mul_f64(self.speed_curve.scale(progress, self.curve_strength) as f64)
     }
 
     #[inline(always)]
     fn reset_time(&mut self, i: usize) {
-        let (start, duration) = &mut self.time[i];
-        *start = self.clock.now();
-        let milli_seconds = self.rng.random_range(self.speed.start..self.speed.end);
-        *duration = Duration::from_millis(milli_seconds);
+        let speed = match self.directions[i] {
+            Direction::Up | Direction::Down => self.vertical_speed,
+            Direction::Left | Direction::Right => self.horizontal_speed,
+        };
+        let base = speed.sample_interval(&mut self.rng);
+        let mut timing = Timing::new(self.clock.now(), base);
+        // The first step has to wait out the slow start of the curve too.
+        timing.interval = self.shape_interval(base, 0.0);
+        self.time[i] = timing;
+        self.schedule(i);
+    }
+
+    /// Keeps the rain inside of `mask` from now on.
+    fn set_mask(&mut self, mut mask: Mask) {
+        mask.layout(self.width, self.height, self.char_width);
+        self.mask = Some(mask);
+    }
+
+    /// Lights the rain up with `picture` from now on.
+    fn set_picture(&mut self, mut picture: Picture) {
+        picture.layout(self.width, self.height);
+        self.picture = Some(picture);
+    }
+
+    /// Takes the characters from `source` from now on. Each drop spells out the next part of the
+    /// text when it starts falling.
+    fn set_source(&mut self, source: Source) {
+        self.source = Some(source);
+        self.spelled.iter_mut().for_each(Vec::clear);
+    }
+
+    /// Has drop `i` spell out the next part of the text over the whole way across the screen, if
+    /// there is more of it.
+    fn take_start(&mut self, i: usize) {
+        let path = match self.directions[i] {
+            Direction::Up | Direction::Down => self.height,
+            Direction::Left | Direction::Right => self.width,
+        };
+        if let Some(source) = &mut self.source {
+            source.take(path, &mut self.spelled[i]);
+        }
     }
 
     #[inline(always)]
@@ -319,9 +1156,9 @@ impl<const LENGTH: usize> Rain<LENGTH> {
 
     #[inline(always)]
     fn reset_window(&mut self, i: usize) {
-        self.windows[i] = self.rng.random_range(
-            Self::MIN_LENGTH_OF_RAIN..self.height.saturating_sub(Self::MAX_LENGTH_OFFSET_OF_RAIN),
-        );
+        self.windows[i] = self
+            .distribution
+            .sample(&mut self.rng, self.min_length, self.max_length);
     }
 
     #[inline(always)]
@@ -334,26 +1171,55 @@ impl<const LENGTH: usize> Rain<LENGTH> {
         if !self.shading {
             return;
         }
-        let base_color: Color = self.body_colors[i].0;
         let window = self.windows[i].saturating_sub(1);
-        let colors = gen_shade_color(base_color, self.shade_gradient, window as u8);
-        self.body_colors[i] = (base_color, Some(colors));
+        let (base_color, colors) = &mut self.body_colors[i];
+        if let Some(colors) = colors {
+            fill_shade_color(colors, *base_color, self.shade_gradient, window);
+        }
+    }
+
+    /// Hands the turn of a finished column over to a random idle one so the number of active
+    /// columns stays the same.
+    #[inline(always)]
+    fn rotate_active(&mut self, i: usize) {
+        if self.idle.is_empty() {
+            return;
+        }
+        self.active[i] = false;
+        self.idle.push(i);
+        let next = self
+            .idle
+            .swap_remove(self.rng.random_range(0..self.idle.len()));
+        self.active[next] = true;
+        self.reset_time(next);
     }
 
     fn reset(&mut self, i: usize) {
+        if self.draining {
+            self.reset_position(i);
+            self.active[i] = false;
+            return;
+        }
         self.reset_time(i);
         self.reset_start(i);
         self.reset_window(i);
         self.reset_position(i);
         self.reset_body_colors(i);
+        self.rotate_active(i);
     }
 
     fn update_screen_buffer(&mut self) -> std::io::Result<()> {
-        for i in self.queue.drain(..).collect::<Vec<usize>>() {
+        // Taken out of self so `reset` can borrow self, handed back below to keep the allocation.
+        let mut queue = std::mem::take(&mut self.queue);
+        for &i in &queue {
+            if self.positions[i] == 0 && self.source.is_some() && !self.redrawing {
+                self.take_start(i);
+            }
             let pos = self.positions[i];
             let start_idx = self.starts[i];
             let window_len = self.windows[i];
             let direction = self.directions[i];
+            let lane = self.lanes[i];
 
             let get_index = |x: usize, y: usize| -> Option<usize> {
                 if x < self.width && y < self.height {
@@ -374,122 +1240,250 @@ impl<const LENGTH: usize> Rain<LENGTH> {
 
             if pos >= window_len {
                 let buf_idx = match direction {
-                    Direction::Down => get_index(i, pos - window_len),
+                    Direction::Down => get_index(lane, pos - window_len),
                     Direction::Up => {
                         let tail_y = self.height.saturating_sub(pos - window_len + 1);
-                        get_index(i, tail_y)
+                        get_index(lane, tail_y)
                     }
-                    Direction::Right => get_index(pos - window_len, i),
+                    Direction::Right => get_index(pos - window_len, lane),
                     Direction::Left => {
                         let tail_x = self.width.saturating_sub(pos - window_len + 1);
-                        get_index(tail_x, i)
+                        get_index(tail_x, lane)
                     }
                 };
                 if let Some(idx) = buf_idx {
-                    self.screen_buffer[idx] = Cell::default();
+                    // Whatever the drop revealed is left behind.
+                    self.screen_buffer[idx] = self
+                        .message
+                        .as_ref()
+                        .map_or_else(Cell::default, |message| message.cell(idx));
                 }
             }
 
             let visible_len = (pos + 1).min(window_len);
             for offset in 0..visible_len {
                 let (x, y) = match direction {
-                    Direction::Down => (i, pos.saturating_sub(offset)),
-                    Direction::Up => (i, self.height.saturating_sub(pos - offset + 1)),
-                    Direction::Right => (pos.saturating_sub(offset), i),
-                    Direction::Left => (self.width.saturating_sub(pos - offset + 1), i),
+                    Direction::Down => (lane, pos.saturating_sub(offset)),
+                    Direction::Up => (lane, self.height.saturating_sub(pos - offset + 1)),
+                    Direction::Right => (pos.saturating_sub(offset), lane),
+                    Direction::Left => (self.width.saturating_sub(pos - offset + 1), lane),
                 };
 
                 if let Some(buf_idx) = get_index(x, y) {
-                    let char_idx = (start_idx + pos - offset) % self.chars.len();
-                    let c = self.chars[char_idx];
+                    if self
+                        .mask
+                        .as_ref()
+                        .is_some_and(|mask| !mask.is_open(buf_idx))
+                    {
+                        continue;
+                    }
+                    let c = if self.source.is_some() {
+                        // Cells past the end of the text the drop got are left blank.
+                        let c = self.spelled[i].get(pos - offset).copied().unwrap_or(' ');
+                        fit_width(c, self.char_width)
+                    } else {
+                        self.chars[(start_idx + pos - offset) % self.chars.len()]
+                    };
                     let color = if offset == 0 {
+                        if let Some(message) = &mut self.message {
+                            message.reveal(buf_idx);
+                        }
                         self.head_colors[i]
                     } else if let Some(fade) = &self.body_colors[i].1 {
                         fade[offset - 1]
                     } else {
                         self.body_colors[i].0
                     };
+                    let color = match &self.picture {
+                        Some(picture) => picture.tint(buf_idx, color, offset == 0),
+                        None => color,
+                    };
                     self.screen_buffer[buf_idx] = Cell::new(c).color(color);
                 }
             }
 
             self.positions[i] += 1;
         }
+        queue.clear();
+        self.queue = queue;
 
         Ok(())
     }
 
-    fn draw_frame(&mut self, w: &mut BufWriter<Stdout>) -> std::io::Result<()> {
-        let total_cells = self.width * self.height;
-        let mut redraw_screen = false;
-
-        for (i, (a, b)) in self
-            .screen_buffer
-            .iter()
-            .zip(&self.previous_screen_buffer)
-            .enumerate()
-        {
-            if a != b {
+    /// Draws every stream where it was last drawn again, for when the screen was cleared while
+    /// the rain wasn't moving.
+    fn redraw(&mut self) -> std::io::Result<()> {
+        for i in 0..self.positions.len() {
+            // Each stream is a step past the one on the screen.
+            if self.active[i] && self.positions[i] > 0 {
+                self.positions[i] -= 1;
                 self.queue.push(i);
             }
-            let is_50_percent_or_more_changed = self.queue.len() > total_cells / 2;
-            if is_50_percent_or_more_changed {
-                redraw_screen = true;
-                break;
-            }
         }
+        self.redrawing = true;
+        let result = self.update_screen_buffer();
+        self.redrawing = false;
+        result
+    }
+}
+
+/// Ends the run once `--duration` has passed or `--frames` have been drawn.
+#[derive(Debug)]
+struct RunLimit {
+    deadline: Option<Instant>,
+    frames: Option<u64>,
+    drawn: u64,
+}
+
+impl RunLimit {
+    fn new(settings: &cli::Cli, now: Instant) -> Self {
+        Self {
+            deadline: settings
+                .duration
+                .and_then(|duration| now.checked_add(duration)),
+            frames: settings.frames,
+            drawn: 0,
+        }
+    }
 
-        if redraw_screen && matches!(self.directions[0], Direction::Left | Direction::Right) {
-            for (y, chunk) in self.screen_buffer.chunks(self.width).enumerate() {
-                let screen = chunk
-                    .iter()
-                    .map(|c| c.display(self.char_width))
-                    .collect::<String>();
-                queue!(w, cursor::MoveTo(0, y as u16), Print(screen))?;
+    /// How long until the deadline, the main loop has to wake up by then.
+    fn timeout(&self, now: Instant) -> Option<Duration> {
+        self.deadline
+            .map(|deadline| deadline.saturating_duration_since(now))
+    }
 
-                self.queue.clear();
-            }
+    fn frame_drawn(&mut self) {
+        self.drawn += 1;
+    }
 
-            return Ok(());
-        } else if redraw_screen {
-            execute!(w, cursor::MoveTo(0, 0))?;
-            let screen = self
-                .screen_buffer
-                .iter()
-                .map(|c| c.display(self.char_width))
-                .collect::<String>();
-            execute!(w, Print(screen))?;
+    fn is_reached(&self, now: Instant) -> bool {
+        self.deadline.is_some_and(|deadline| now >= deadline)
+            || self.frames.is_some_and(|frames| self.drawn >= frames)
+    }
+}
 
-            self.queue.clear();
-            return Ok(());
+/// Caps the frame rate and works out how long the main loop can sleep before it has work to do.
+#[derive(Debug)]
+struct FrameScheduler {
+    /// Shortest time between two frames
+    frame_time: Duration,
+    /// When the last frame was drawn
+    last_frame: Option<Instant>,
+}
+
+impl FrameScheduler {
+    fn new(fps: u32) -> Self {
+        Self {
+            frame_time: Duration::from_secs(1) / fps.max(1),
+            last_frame: None,
         }
+    }
 
-        for idx in self.queue.drain(..) {
-            let cell = &self.screen_buffer[idx];
-            let x = (idx % self.width) * self.char_width;
-            let y = idx / self.width;
+    /// Earliest time the next frame is allowed to be drawn.
+    fn next_frame(&self, now: Instant) -> Instant {
+        self.last_frame.map_or(now, |last| last + self.frame_time)
+    }
 
-            if cell.is_visible() {
-                queue!(
-                    w,
-                    cursor::MoveTo(x as u16, y as u16),
-                    SetForegroundColor(cell.color),
-                    Print(cell.char)
-                )?;
-            } else {
-                queue!(
-                    w,
-                    cursor::MoveTo(x as u16, y as u16),
-                    SetForegroundColor(cell.color),
-                    Print(" ".repeat(self.char_width))
-                )?;
-            }
+    /// How long to wait for input before drawing the next frame.
+    /// Sleeps until the next column is due, but never wakes up faster than the frame rate allows.
+    fn timeout(&self, now: Instant, next_due: Option<Instant>) -> Duration {
+        let wake_up = match next_due {
+            Some(due) => due.max(self.next_frame(now)),
+            None => now + IDLE_POLL_INTERVAL,
+        };
+        wake_up.saturating_duration_since(now)
+    }
+
+    fn is_frame_ready(&self, now: Instant) -> bool {
+        now >= self.next_frame(now)
+    }
+
+    fn frame_drawn(&mut self, now: Instant) {
+        self.last_frame = Some(now);
+    }
+}
+
+/// Things drawn over the rain. They go on a copy of the screen buffer, so the rain underneath
+/// is never touched and the renderer puts it back as soon as an overlay goes away.
+#[derive(Debug)]
+struct Overlays {
+    hud: Hud,
+    wall_clock: Option<WallClock>,
+    countdown: Option<Countdown>,
+    /// Rain with the overlays on top, kept around to reuse the allocation
+    frame: Vec<Cell>,
+}
 
-            self.previous_screen_buffer[idx] = *cell;
+impl Overlays {
+    fn new(settings: &cli::Cli, now: Instant) -> Self {
+        let color = settings.clock_color().into();
+        Self {
+            hud: Hud::default(),
+            wall_clock: settings.clock.then(|| WallClock::new(color)),
+            countdown: settings
+                .countdown
+                .map(|duration| Countdown::new(duration, color, settings.bell, now)),
+            frame: Vec::new(),
         }
+    }
 
-        Ok(())
+    fn is_shown(&self) -> bool {
+        self.hud.visible || self.wall_clock.is_some() || self.countdown.is_some()
+    }
+
+    /// Brings the clocks up to date and returns whether one of them changed.
+    fn tick(&mut self, now: Instant) -> bool {
+        let wall_clock = self.wall_clock.as_mut().is_some_and(WallClock::tick);
+        let countdown = self
+            .countdown
+            .as_mut()
+            .is_some_and(|countdown| countdown.tick(now));
+        wall_clock || countdown
+    }
+
+    /// When one of the clocks changes next.
+    fn next_tick(&self, now: Instant) -> Option<Instant> {
+        let wall_clock = self
+            .wall_clock
+            .as_ref()
+            .map(|_| now + WallClock::until_next_tick());
+        let countdown = self
+            .countdown
+            .as_ref()
+            .map(|countdown| countdown.next_change(now));
+        wall_clock.into_iter().chain(countdown).min()
     }
+
+    fn compose<const LENGTH: usize>(
+        &mut self,
+        rain: &Rain<LENGTH>,
+        settings: &cli::Cli,
+        stats: FrameStats,
+    ) -> &[Cell] {
+        self.frame.clear();
+        self.frame.extend_from_slice(&rain.screen_buffer);
+        if let Some(wall_clock) = &self.wall_clock {
+            wall_clock.draw(&mut self.frame, rain.width, rain.height, rain.char_width);
+        }
+        if let Some(countdown) = &self.countdown {
+            countdown.draw(&mut self.frame, rain.width, rain.height, rain.char_width);
+        }
+        if self.hud.visible {
+            self.hud.draw(&mut self.frame, rain, settings, stats);
+        }
+        &self.frame
+    }
+}
+
+/// What the settings point to outside of themselves, read in before the rain starts.
+#[derive(Debug, Default)]
+struct Resources {
+    /// ASCII art read from --mask-file
+    mask_art: Option<String>,
+    /// Picture read from --image
+    picture: Option<image::Image>,
+    /// Text read from --source
+    source: Option<Source>,
 }
 
 struct App {
@@ -505,82 +1499,506 @@ impl Default for App {
 }
 
 impl App {
-    fn run(&mut self, settings: cli::Cli) -> std::io::Result<()> {
+    /// How much faster or slower the rain gets with each press of `+` or `-`
+    const SPEED_STEP: f32 = 1.25;
+
+    /// Runs the rain until it is quit, returning the exit status of the process.
+    fn run(&mut self, mut settings: cli::Cli, resources: Resources) -> std::io::Result<u8> {
         let (w, h) = terminal::size()?;
         let mut rain = Rain::<1024>::new(w as usize, h as usize, &settings, SystemClock);
+        if let Some(art) = &resources.mask_art {
+            rain.set_mask(Mask::from_art(art, settings.invert_mask));
+        }
+        if let Some(image) = resources.picture {
+            rain.set_picture(Picture::new(image, settings.image_mode));
+        }
+        if let Some(source) = resources.source {
+            rain.set_source(source);
+        }
+        Self::install_panic_hook();
         self.setup_terminal(&settings)?;
 
+        let render_thread = RenderThread::spawn(
+            Self::renderer(&rain, &settings),
+            BufWriter::with_capacity(640_000, stdout()),
+        );
+        let mut scheduler = FrameScheduler::new(settings.fps);
+        let mut limit = RunLimit::new(&settings, rain.clock.now());
+        let signals = Signals::register()?;
+        let mut overlays = Overlays::new(&settings, rain.clock.now());
+        let mut intro = match &settings.intro {
+            Some(script) if !settings.no_intro => Some(Intro::new(script.clone(), SystemClock)),
+            _ => None,
+        };
+        if intro.is_some() {
+            // The rain waits for the intro to finish.
+            rain.clock.pause();
+        }
+        let mut status = 0;
         let mut is_running = true;
         while is_running {
-            if event::poll(POLL_INTERVAL)? {
-                match event::read()? {
-                    event::Event::Key(key) if Self::is_exit_key(&key) => {
+            // Set when the screen has to be drawn again even though the rain didn't move.
+            let mut redraw = false;
+            if signals.terminate.load(Ordering::Relaxed) {
+                break;
+            }
+            if signals.suspend.swap(false, Ordering::Relaxed) {
+                Self::suspend(&render_thread)?;
+            }
+            if signals.resume.swap(false, Ordering::Relaxed) {
+                self.resume(
+                    &rain,
+                    &settings,
+                    &mut overlays,
+                    intro.as_mut(),
+                    &render_thread,
+                )?;
+                continue;
+            }
+
+            let (now, next_due) = match &intro {
+                Some(intro) => (intro.now(), Some(intro.next_change())),
+                None if rain.is_paused() => (rain.clock.now(), None),
+                None => (rain.clock.now(), rain.next_due()),
+            };
+            let mut timeout = scheduler.timeout(now, next_due);
+            // The countdown stands still with the rain, the wall clock never does.
+            let ticking = if rain.is_paused() {
+                overlays
+                    .wall_clock
+                    .as_ref()
+                    .map(|_| now + WallClock::until_next_tick())
+            } else {
+                overlays.next_tick(now)
+            };
+            if let Some(tick) = ticking {
+                timeout = timeout.min(tick.saturating_duration_since(now));
+            }
+            let timeout = match limit.timeout(now) {
+                Some(limit) if !rain.draining => limit.min(timeout),
+                _ => timeout,
+            };
+            if event::poll(timeout)? {
+                let event = event::read()?;
+                if let Some(code) = settings.screensaver
+                    && Self::is_wake_event(&event)
+                {
+                    status = code;
+                    break;
+                }
+                match event {
+                    // Any key but quit and suspend skips the intro.
+                    event::Event::Key(key)
+                        if key.kind == event::KeyEventKind::Press && intro.is_some() =>
+                    {
+                        match settings.keys.action(&key) {
+                            Some(Action::Quit) => is_running = false,
+                            Some(Action::Suspend) => {
+                                Self::suspend(&render_thread)?;
+                                // Set on the way back, the terminal is taken back below.
+                                signals.resume.store(false, Ordering::Relaxed);
+                                self.resume(
+                                    &rain,
+                                    &settings,
+                                    &mut overlays,
+                                    intro.as_mut(),
+                                    &render_thread,
+                                )?;
+                                continue;
+                            }
+                            _ => {
+                                intro = None;
+                                Self::start_rain(&mut rain, &mut scheduler, &settings);
+                            }
+                        }
+                    }
+                    // Any key skips the rain falling off the screen on the way out.
+                    event::Event::Key(key)
+                        if key.kind == event::KeyEventKind::Press && rain.draining =>
+                    {
                         is_running = false;
                     }
+                    event::Event::Key(key) if key.kind == event::KeyEventKind::Press => {
+                        match settings.keys.action(&key) {
+                            Some(Action::Quit) => {
+                                if rain.is_paused() {
+                                    rain.toggle_pause();
+                                }
+                                rain.drain();
+                                is_running = !rain.is_drained();
+                            }
+                            Some(Action::Suspend) => {
+                                Self::suspend(&render_thread)?;
+                                // Set on the way back, the terminal is taken back below.
+                                signals.resume.store(false, Ordering::Relaxed);
+                                self.resume(
+                                    &rain,
+                                    &settings,
+                                    &mut overlays,
+                                    intro.as_mut(),
+                                    &render_thread,
+                                )?;
+                                continue;
+                            }
+                            Some(Action::Step) if rain.is_paused() => {
+                                rain.step();
+                                rain.update_screen_buffer()?;
+                                Self::submit(&rain, &settings, &mut overlays, &render_thread)?;
+                            }
+                            Some(Action::ToggleHud) => {
+                                overlays.hud.toggle();
+                                Self::submit(&rain, &settings, &mut overlays, &render_thread)?;
+                            }
+                            Some(action) => {
+                                let size = (rain.width, rain.height, rain.char_width);
+                                Self::control(action, &mut rain, &mut settings);
+                                if size != (rain.width, rain.height, rain.char_width) {
+                                    render_thread.set_renderer(Self::renderer(&rain, &settings));
+                                }
+                                redraw = true;
+                            }
+                            None => {}
+                        }
+                    }
                     event::Event::Resize(w, h) => {
-                        // TODO: make a method that handle resizing so we dont regenerate the rain
-                        rain = Rain::<1024>::new(w as usize, h as usize, &settings, SystemClock);
-                        queue!(self.stdout, terminal::Clear(terminal::ClearType::All))?;
+                        rain.resize(w as usize, h as usize, &settings);
+                        render_thread.set_renderer(Self::renderer(&rain, &settings));
+                        redraw = true;
                     }
                     _ => {}
                 }
             }
 
+            if let Some(playing) = &mut intro {
+                let now = playing.now();
+                if playing.is_done() {
+                    intro = None;
+                    Self::start_rain(&mut rain, &mut scheduler, &settings);
+                } else if scheduler.is_frame_ready(now) {
+                    let (r, g, b) = settings.rain_color();
+                    let color = Color::Rgb { r, g, b };
+                    render_thread.submit(playing.frame(
+                        rain.width,
+                        rain.height,
+                        rain.char_width,
+                        color,
+                    ))?;
+                    scheduler.frame_drawn(now);
+                }
+                continue;
+            }
+
+            let now = rain.clock.now();
+            let ticked = overlays.tick(now);
+            if let Some(countdown) = &mut overlays.countdown {
+                if let Some(factor) = countdown.take_speed_change() {
+                    rain.change_speed(factor);
+                }
+                if countdown.take_ring() {
+                    render_thread.sync()?;
+                    execute!(self.stdout, Print('\x07'))?;
+                }
+            }
+
+            if rain.is_paused() || !scheduler.is_frame_ready(now) {
+                // Paused rain has no next frame to wait for, a screen that was cleared or changed
+                // is drawn again right away.
+                let redraw = redraw && rain.is_paused();
+                if redraw {
+                    rain.redraw()?;
+                }
+                // Clocks that changed don't wait for the next frame of rain.
+                if ticked || redraw {
+                    Self::submit(&rain, &settings, &mut overlays, &render_thread)?;
+                }
+                continue;
+            }
+
             rain.update();
             rain.update_screen_buffer()?;
-            rain.draw_frame(&mut self.stdout)?;
+            Self::submit(&rain, &settings, &mut overlays, &render_thread)?;
+            scheduler.frame_drawn(now);
+            limit.frame_drawn();
 
-            self.stdout.flush()?;
+            if rain.is_drained() {
+                break;
+            }
+            if limit.is_reached(now) && !rain.draining {
+                if !settings.drain {
+                    break;
+                }
+                rain.drain();
+            }
         }
 
-        Ok(())
+        Ok(status)
+    }
+
+    /// Lets the rain go once the intro is over. The frames of the intro were timed on a clock of
+    /// their own, so frame timing starts over.
+    fn start_rain<const LENGTH: usize>(
+        rain: &mut Rain<LENGTH>,
+        scheduler: &mut FrameScheduler,
+        settings: &cli::Cli,
+    ) {
+        rain.clock.resume();
+        *scheduler = FrameScheduler::new(settings.fps);
+    }
+
+    /// Hands the screen to the render thread, with the overlays that are shown on top of the rain.
+    fn submit<const LENGTH: usize>(
+        rain: &Rain<LENGTH>,
+        settings: &cli::Cli,
+        overlays: &mut Overlays,
+        render_thread: &RenderThread,
+    ) -> std::io::Result<()> {
+        if !overlays.is_shown() {
+            return render_thread.submit(&rain.screen_buffer);
+        }
+        render_thread.submit(overlays.compose(rain, settings, render_thread.stats()))
+    }
+
+    /// Whether `event` should end the screensaver.
+    fn is_wake_event(event: &event::Event) -> bool {
+        match event {
+            event::Event::Key(key) => key.kind == event::KeyEventKind::Press,
+            event::Event::Mouse(_)
+            | event::Event::Paste(_)
+            | event::Event::FocusGained
+            | event::Event::FocusLost => true,
+            event::Event::Resize(..) => false,
+        }
+    }
+
+    /// Changes the running rain for the live control keys.
+    fn control<const LENGTH: usize>(
+        action: Action,
+        rain: &mut Rain<LENGTH>,
+        settings: &mut cli::Cli,
+    ) {
+        let direction = match action {
+            Action::Pause => {
+                rain.toggle_pause();
+                return;
+            }
+            Action::SpeedUp => {
+                rain.change_speed(Self::SPEED_STEP);
+                return;
+            }
+            Action::SlowDown => {
+                rain.change_speed(1.0 / Self::SPEED_STEP);
+                return;
+            }
+            Action::NextGroup => {
+                settings.group = settings.group.next();
+                rain.set_group(settings);
+                return;
+            }
+            Action::NextColor => {
+                settings.color = cli::next_color(&settings.color).to_string();
+                rain.set_colors(settings);
+                return;
+            }
+            Action::ToggleShade => {
+                settings.shade = !settings.shade;
+                rain.set_colors(settings);
+                return;
+            }
+            Action::Up => Direction::Up,
+            Action::Down => Direction::Down,
+            Action::Left => Direction::Left,
+            Action::Right => Direction::Right,
+            Action::Quit | Action::Suspend | Action::Step | Action::ToggleHud => return,
+        };
+        if direction != settings.direction {
+            settings.direction = direction;
+            rain.set_direction(settings);
+        }
+    }
+
+    fn renderer<const LENGTH: usize>(rain: &Rain<LENGTH>, settings: &cli::Cli) -> Renderer {
+        Renderer::new(rain.width, rain.height, rain.char_width)
+            .sync(!settings.no_sync)
+            .redraw_threshold(settings.redraw_threshold)
+    }
+
+    fn install_panic_hook() {
+        let previous_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            // Leave the alternate screen first so the message ends up where the user can read it.
+            restore_terminal();
+            previous_hook(info);
+        }));
     }
 
     #[inline(always)]
     fn setup_terminal(&mut self, settings: &cli::Cli) -> std::io::Result<()> {
-        terminal::enable_raw_mode()?;
-        execute!(self.stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+        Self::enable_raw_mode(settings)?;
+        setup_terminal_to(&mut self.stdout, settings)
+    }
+
+    /// Switches the terminal to raw mode, after marking it as one `restore_terminal` has to put
+    /// back.
+    fn enable_raw_mode(settings: &cli::Cli) -> std::io::Result<()> {
+        TERMINAL_ACTIVE.store(true, Ordering::SeqCst);
+        INPUT_CAPTURE.store(settings.screensaver.is_some(), Ordering::SeqCst);
+        terminal::enable_raw_mode()
+    }
+
+    /// Takes the terminal back once the process is continued and draws the whole screen on it
+    /// again, whatever the terminal showed while we were stopped. The main loop starts over after
+    /// this, so nothing is drawn before the terminal is set up.
+    fn resume<const LENGTH: usize>(
+        &mut self,
+        rain: &Rain<LENGTH>,
+        settings: &cli::Cli,
+        overlays: &mut Overlays,
+        intro: Option<&mut Intro>,
+        render_thread: &RenderThread,
+    ) -> std::io::Result<()> {
+        Self::enable_raw_mode(settings)?;
+        Self::resume_to(
+            &mut self.stdout,
+            rain,
+            settings,
+            overlays,
+            intro,
+            render_thread,
+        )
+    }
 
-        if let Some(col) = settings.rain_bg_color() {
-            execute!(
-                self.stdout,
-                SetBackgroundColor(col.into()),
-                Clear(ClearType::All),
-            )?
+    /// Same as `resume`, with the terminal set up through `out`.
+    fn resume_to<const LENGTH: usize>(
+        out: &mut impl std::io::Write,
+        rain: &Rain<LENGTH>,
+        settings: &cli::Cli,
+        overlays: &mut Overlays,
+        intro: Option<&mut Intro>,
+        render_thread: &RenderThread,
+    ) -> std::io::Result<()> {
+        setup_terminal_to(out, settings)?;
+        // A new renderer clears the screen and draws every cell.
+        render_thread.set_renderer(Self::renderer(rain, settings));
+        match intro {
+            Some(intro) => {
+                let (r, g, b) = settings.rain_color();
+                let color = Color::Rgb { r, g, b };
+                render_thread.submit(intro.frame(rain.width, rain.height, rain.char_width, color))
+            }
+            None => Self::submit(rain, settings, overlays, render_thread),
         }
+    }
+
+    /// Hands the terminal back to the shell and stops the process like Ctrl-Z normally would.
+    /// Returns once the process is continued, `App::resume` takes it from there.
+    #[cfg(unix)]
+    fn suspend(render_thread: &RenderThread) -> std::io::Result<()> {
+        render_thread.sync()?;
+        restore_terminal();
+        signal_hook::low_level::emulate_default_handler(signal_hook::consts::SIGTSTP)
+    }
 
+    #[cfg(not(unix))]
+    fn suspend(_: &RenderThread) -> std::io::Result<()> {
         Ok(())
     }
+}
 
-    fn is_exit_key(key: &event::KeyEvent) -> bool {
-        matches!(
-            *key,
-            event::KeyEvent {
-                code: event::KeyCode::Char('c'),
-                modifiers: event::KeyModifiers::CONTROL,
-                ..
-            } | event::KeyEvent {
-                code: event::KeyCode::Esc,
-                ..
-            } | event::KeyEvent {
-                code: event::KeyCode::Char('q' | 'Q'),
-                modifiers: event::KeyModifiers::NONE,
-                ..
-            }
-        )
+/// Flags set from signal handlers for the main loop to act on.
+#[derive(Debug, Default)]
+struct Signals {
+    /// Asked to terminate, the main loop stops and restores the terminal on its way out
+    terminate: Arc<AtomicBool>,
+    /// Asked to stop from outside, with `kill -TSTP`
+    suspend: Arc<AtomicBool>,
+    /// Continued after being stopped
+    resume: Arc<AtomicBool>,
+}
+
+impl Signals {
+    #[cfg(unix)]
+    fn register() -> std::io::Result<Self> {
+        use signal_hook::consts::{SIGCONT, SIGHUP, SIGINT, SIGTERM, SIGTSTP};
+
+        let signals = Self::default();
+        for signal in [SIGTERM, SIGHUP, SIGINT] {
+            signal_hook::flag::register(signal, Arc::clone(&signals.terminate))?;
+        }
+        signal_hook::flag::register(SIGTSTP, Arc::clone(&signals.suspend))?;
+        signal_hook::flag::register(SIGCONT, Arc::clone(&signals.resume))?;
+        Ok(signals)
+    }
+
+    #[cfg(not(unix))]
+    fn register() -> std::io::Result<Self> {
+        Ok(Self::default())
     }
 }
 
 impl Drop for App {
     fn drop(&mut self) {
-        execute!(self.stdout, cursor::Show, terminal::LeaveAlternateScreen)
-            .expect("failed to leave alternate screen");
-        terminal::disable_raw_mode().expect("failed to disable raw mode");
+        restore_terminal();
+    }
+}
+
+/// Writes the escape sequences `App::setup_terminal` sets the terminal up with to `out`.
+fn setup_terminal_to(out: &mut impl std::io::Write, settings: &cli::Cli) -> std::io::Result<()> {
+    execute!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+
+    if settings.screensaver.is_some() {
+        execute!(
+            out,
+            event::EnableMouseCapture,
+            event::EnableFocusChange,
+            event::EnableBracketedPaste,
+        )?;
+    }
+
+    if let Some(col) = settings.rain_bg_color() {
+        execute!(out, SetBackgroundColor(col.into()), Clear(ClearType::All))?
     }
+
+    Ok(())
+}
+
+/// Puts the terminal back the way it was before `App::setup_terminal`.
+/// Runs from `Drop` and the panic hook, so it never panics and does nothing the second time.
+fn restore_terminal() {
+    restore_terminal_to(&mut stdout());
+}
+
+/// Same as `restore_terminal`, with the escape sequences written to `out`.
+fn restore_terminal_to(out: &mut impl std::io::Write) {
+    if !TERMINAL_ACTIVE.swap(false, Ordering::SeqCst) {
+        return;
+    }
+    if INPUT_CAPTURE.swap(false, Ordering::SeqCst) {
+        let _ = execute!(
+            out,
+            event::DisableMouseCapture,
+            event::DisableFocusChange,
+            event::DisableBracketedPaste,
+        );
+    }
+    let _ = execute!(
+        out,
+        ResetColor,
+        cursor::Show,
+        terminal::LeaveAlternateScreen
+    );
+    let _ = terminal::disable_raw_mode();
 }
 
 /// Generates a vector of Colors that fade to `black` over the length of the column.
-fn gen_shade_color(base: Color, shade: Color, length: u8) -> Vec<Color> {
+fn gen_shade_color(base: Color, shade: Color, length: usize) -> Vec<Color> {
+    let mut colors = Vec::with_capacity(length);
+    fill_shade_color(&mut colors, base, shade, length);
+    colors
+}
+
+/// Same as `gen_shade_color` but reuses the allocation of `colors`.
+fn fill_shade_color(colors: &mut Vec<Color>, base: Color, shade: Color, length: usize) {
+    colors.clear();
     let (
         Color::Rgb {
             r: br,
@@ -594,10 +2012,9 @@ fn gen_shade_color(base: Color, shade: Color, length: u8) -> Vec<Color> {
         },
     ) = (base, shade)
     else {
-        return Vec::new();
+        return;
     };
 
-    let mut colors = Vec::with_capacity(length as usize);
     let br = br as f32;
     let bg = bg as f32;
     let bb = bb as f32;
@@ -612,13 +2029,15 @@ fn gen_shade_color(base: Color, shade: Color, length: u8) -> Vec<Color> {
         let b = ((bb * (1.0 - t)) + (sb * t)) as u8;
         colors.push(Color::Rgb { r, g, b });
     }
-    colors
 }
 
 fn update_settings_with_config(settings: &mut cli::Cli) {
-    let Some(config) = cli::load_config() else {
-        return;
-    };
+    if let Some(config) = cli::load_config() {
+        apply_config(settings, config);
+    }
+}
+
+fn apply_config(settings: &mut cli::Cli, config: cli::Config) {
     if let Some(shade) = config.shade {
         settings.shade = shade;
     }
@@ -635,11 +2054,100 @@ fn update_settings_with_config(settings: &mut cli::Cli) {
         settings.direction = direction;
     }
     if let Some(speed) = config.speed {
-        settings.speed = speed;
+        match Speed::from_str(&speed) {
+            Ok(speed) => settings.speed = speed,
+            Err(err) => eprintln!("{err}"),
+        }
+    }
+    if let Some(horizontal_speed) = config.horizontal_speed {
+        match Speed::from_str(&horizontal_speed) {
+            Ok(speed) => settings.horizontal_speed = Some(speed),
+            Err(err) => eprintln!("{err}"),
+        }
     }
     if let Some(display_group) = config.display_group {
         settings.display_group = display_group;
     }
+    if let Some(fps) = config.fps {
+        match cli::check_fps(fps) {
+            Ok(fps) => settings.fps = fps,
+            Err(err) => eprintln!("fps {err}"),
+        }
+    }
+    if let Some(keys) = &config.keys {
+        settings.keys.configure(keys);
+    }
+    if let Some(intro) = &config.intro {
+        match intro::Script::try_from(intro) {
+            Ok(script) => settings.intro = Some(script),
+            Err(err) => eprintln!("intro {err}"),
+        }
+    }
+    if let Some(sync) = config.sync {
+        settings.no_sync = !sync;
+    }
+    if let Some(redraw_threshold) = config.redraw_threshold {
+        match cli::check_fraction(redraw_threshold) {
+            Ok(threshold) => settings.redraw_threshold = threshold,
+            Err(err) => eprintln!("redraw_threshold {err}"),
+        }
+    }
+    if let Some(clock) = config.clock {
+        // Both go in the middle of the screen, a countdown asked for on the command line wins.
+        if clock && settings.countdown.is_some() {
+            eprintln!("clock is not shown with --countdown");
+        } else {
+            settings.clock = clock;
+        }
+    }
+    if let Some(clock_color) = config.clock_color {
+        settings.clock_color = clock_color;
+    }
+    if let Some(message_color) = config.message_color {
+        settings.message_color = message_color;
+    }
+    if let Some(column_gap) = config.column_gap {
+        match cli::check_column_gap(column_gap) {
+            Ok(column_gap) => settings.column_gap = column_gap,
+            Err(err) => eprintln!("column_gap {err}"),
+        }
+    }
+    if let Some(sparse) = config.sparse {
+        match cli::check_fraction(sparse) {
+            Ok(sparse) => settings.sparse = Some(sparse),
+            Err(err) => eprintln!("sparse {err}"),
+        }
+    }
+    if let Some(speed_curve) = config.speed_curve {
+        settings.speed_curve = speed_curve;
+    }
+    if let Some(curve_strength) = config.curve_strength {
+        match cli::check_curve_strength(curve_strength) {
+            Ok(strength) => settings.curve_strength = strength,
+            Err(err) => eprintln!("curve_strength {err}"),
+        }
+    }
+    if let Some(min_length) = config.min_length {
+        match TrailLength::from_str(&min_length) {
+            Ok(length) => settings.min_length = length,
+            Err(err) => eprintln!("{err}"),
+        }
+    }
+    if let Some(max_length) = config.max_length {
+        match TrailLength::from_str(&max_length) {
+            Ok(length) => settings.max_length = Some(length),
+            Err(err) => eprintln!("{err}"),
+        }
+    }
+    if let Some(length_distribution) = config.length_distribution {
+        settings.length_distribution = length_distribution;
+    }
+    if let Some(density) = config.density {
+        match cli::check_fraction(density) {
+            Ok(density) => settings.density = density,
+            Err(err) => eprintln!("density {err}"),
+        }
+    }
     let Some(name) = config.group else {
         return;
     };
@@ -657,10 +2165,61 @@ fn update_settings_with_config(settings: &mut cli::Cli) {
     }
 }
 
-fn main() -> std::io::Result<()> {
+fn main() -> std::io::Result<std::process::ExitCode> {
     let mut settings = cli::Cli::parse();
 
     update_settings_with_config(&mut settings);
+    let mut resources = Resources::default();
+
+    if let Some(path) = &settings.message_file {
+        match std::fs::read_to_string(path) {
+            Ok(message) => settings.message = Some(message),
+            Err(err) => {
+                eprintln!("{}: {err}", path.display());
+                return Ok(std::process::ExitCode::FAILURE);
+            }
+        }
+    }
+
+    if let Some(path) = &settings.mask_file {
+        match std::fs::read_to_string(path) {
+            Ok(art) => resources.mask_art = Some(art),
+            Err(err) => {
+                eprintln!("{}: {err}", path.display());
+                return Ok(std::process::ExitCode::FAILURE);
+            }
+        }
+    }
+
+    if let Some(path) = &settings.source {
+        let source = if path.as_os_str() == "-" {
+            let stdin = std::io::stdin();
+            if stdin.is_terminal() {
+                eprintln!("--source -: nothing is piped in to read the text from");
+                return Ok(std::process::ExitCode::FAILURE);
+            }
+            Source::stream(BufReader::new(stdin))
+        } else {
+            match std::fs::read_to_string(path) {
+                Ok(text) => Source::text(&text),
+                Err(err) => {
+                    eprintln!("{}: {err}", path.display());
+                    return Ok(std::process::ExitCode::FAILURE);
+                }
+            }
+        };
+        resources.source = Some(source);
+    }
+
+    if let Some(path) = &settings.image {
+        match image::Image::load(path) {
+            Ok(image) => resources.picture = Some(image),
+            Err(err) => {
+                eprintln!("{}: {err}", path.display());
+                return Ok(std::process::ExitCode::FAILURE);
+            }
+        }
+    }
 
     if settings.display_group {
         // These two groups don't render right if they dont have enough space.
@@ -692,8 +2251,9 @@ fn main() -> std::io::Result<()> {
                 }
             }
         }
-        return Ok(());
+        return Ok(std::process::ExitCode::SUCCESS);
     }
 
-    App::default().run(settings)
+    let status = App::default().run(settings, resources)?;
+    Ok(std::process::ExitCode::from(status))
 }
@@ -0,0 +1,110 @@
+use crate::font;
+
+/// Shape the rain is kept to. The rain only shows in the cells the shape covers, or only outside
+/// of it when it is inverted.
+#[derive(Debug)]
+pub struct Mask {
+    /// Pixels of the shape
+    bitmap: Vec<Vec<bool>>,
+    /// Terminal columns a pixel takes up
+    pixel_columns: usize,
+    /// Rain shows outside of the shape instead of inside
+    invert: bool,
+    /// Whether rain shows in each cell of the screen
+    open: Vec<bool>,
+}
+
+impl Mask {
+    /// Shape of `text` in the block font, lines of it stacked with a blank row between them.
+    pub fn from_text(text: &str, invert: bool) -> Self {
+        let lines: Vec<_> = text.trim_matches('\n').lines().map(font::bitmap).collect();
+        let width = lines
+            .iter()
+            .map(|line| line[0].len())
+            .max()
+            .unwrap_or_default();
+        let mut bitmap = Vec::new();
+        for (i, line) in lines.into_iter().enumerate() {
+            if i > 0 {
+                bitmap.push(vec![false; width]);
+            }
+            for row in line {
+                let left = (width - row.len()) / 2;
+                let mut padded = vec![false; width];
+                padded[left..left + row.len()].copy_from_slice(&row);
+                bitmap.push(padded);
+            }
+        }
+        Self::new(bitmap, font::pixel_width(1), invert)
+    }
+
+    /// Shape drawn in ASCII art, every character other than a space is part of it.
+    pub fn from_art(art: &str, invert: bool) -> Self {
+        let lines: Vec<_> = art.trim_matches('\n').lines().map(str::trim_end).collect();
+        let width = lines
+            .iter()
+            .map(|line| line.chars().count())
+            .max()
+            .unwrap_or_default();
+        let bitmap = lines
+            .iter()
+            .map(|line| {
+                let mut row: Vec<_> = line.chars().map(|c| !c.is_whitespace()).collect();
+                row.resize(width, false);
+                row
+            })
+            .collect();
+        Self::new(bitmap, 1, invert)
+    }
+
+    fn new(bitmap: Vec<Vec<bool>>, pixel_columns: usize, invert: bool) -> Self {
+        Self {
+            bitmap,
+            pixel_columns,
+            invert,
+            open: Vec::new(),
+        }
+    }
+
+    /// Blows the shape up as far as it goes on a screen of `width` by `height` cells and centers
+    /// it. Shapes too big for the screen are cut off at both ends.
+    pub fn layout(&mut self, width: usize, height: usize, char_width: usize) {
+        self.open.clear();
+        self.open.resize(width * height, self.invert);
+
+        let rows = self.bitmap.len();
+        let columns = self.bitmap.first().map_or(0, Vec::len) * self.pixel_columns;
+        if rows == 0 || columns == 0 {
+            return;
+        }
+        let screen_columns = width * char_width;
+        let scale = (screen_columns / columns).min(height / rows).max(1);
+        let (columns, rows) = (columns * scale, rows * scale);
+        // Offsets are in terminal columns and rows, negative when the shape is cut off.
+        let left = (screen_columns as isize - columns as isize) / 2;
+        let top = (height as isize - rows as isize) / 2;
+        for y in 0..height {
+            let Ok(py) = usize::try_from(y as isize - top) else {
+                continue;
+            };
+            let Some(row) = self.bitmap.get(py / scale) else {
+                break;
+            };
+            for x in 0..width {
+                // The middle of the cell decides for cells wider than a column.
+                let column = (x * char_width + char_width / 2) as isize - left;
+                let Ok(column) = usize::try_from(column) else {
+                    continue;
+                };
+                if row.get(column / scale / self.pixel_columns) == Some(&true) {
+                    self.open[y * width + x] = !self.invert;
+                }
+            }
+        }
+    }
+
+    /// Whether rain shows in the cell at `idx`.
+    pub fn is_open(&self, idx: usize) -> bool {
+        self.open.get(idx).copied().unwrap_or(self.invert)
+    }
+}
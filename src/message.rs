@@ -0,0 +1,88 @@
+use crate::{Cell, fit_width};
+use crossterm::style::Color;
+
+/// Text hidden behind the rain. Drops that fall through one of its cells leave the character
+/// behind, so the message shows up bit by bit as the rain passes over it.
+#[derive(Debug)]
+pub struct Message {
+    /// Characters of the message, without the line breaks
+    chars: Vec<char>,
+    /// Range of `chars` on each line
+    lines: Vec<std::ops::Range<usize>>,
+    /// Which characters a drop has passed through
+    revealed: Vec<bool>,
+    /// Index into `chars` for every cell of the screen holding a part of the message
+    mask: Vec<Option<usize>>,
+    color: Color,
+    /// Terminal columns taken up by a single cell
+    char_width: usize,
+}
+
+impl Message {
+    pub fn new(text: &str, color: Color) -> Self {
+        let mut chars = Vec::new();
+        let mut lines = Vec::new();
+        for line in text.trim_matches('\n').lines() {
+            let start = chars.len();
+            chars.extend(line.trim_end().chars().map(|c| match c {
+                '\t' => ' ',
+                c => c,
+            }));
+            lines.push(start..chars.len());
+        }
+        Self {
+            revealed: vec![false; chars.len()],
+            chars,
+            lines,
+            mask: Vec::new(),
+            color,
+            char_width: 1,
+        }
+    }
+
+    /// Centers the message on a screen of `width` by `height` cells. Lines that don't fit are
+    /// cut off at both ends, and what has been revealed stays revealed.
+    pub fn layout(&mut self, width: usize, height: usize, char_width: usize) {
+        self.char_width = char_width;
+        self.mask.clear();
+        self.mask.resize(width * height, None);
+
+        let top = height.saturating_sub(self.lines.len()) / 2;
+        let skipped = self.lines.len().saturating_sub(height) / 2;
+        for (y, line) in (top..height).zip(self.lines.iter().skip(skipped)) {
+            let left = width.saturating_sub(line.len()) / 2;
+            let cut = line.len().saturating_sub(width) / 2;
+            for (x, i) in (left..width).zip(line.clone().skip(cut)) {
+                if self.chars[i] != ' ' {
+                    self.mask[y * width + x] = Some(i);
+                }
+            }
+        }
+    }
+
+    /// Marks the character under the cell at `idx` as revealed.
+    pub fn reveal(&mut self, idx: usize) {
+        if let Some(Some(i)) = self.mask.get(idx) {
+            self.revealed[*i] = true;
+        }
+    }
+
+    /// What is left in the cell at `idx` once the rain has passed.
+    pub fn cell(&self, idx: usize) -> Cell {
+        match self.mask.get(idx) {
+            Some(Some(i)) if self.revealed[*i] => {
+                Cell::new(fit_width(self.chars[*i], self.char_width)).color(self.color)
+            }
+            _ => Cell::default(),
+        }
+    }
+
+    /// Draws the revealed characters into a cleared screen buffer.
+    pub fn draw(&self, cells: &mut [Cell]) {
+        for (idx, cell) in cells.iter_mut().enumerate() {
+            if matches!(self.mask.get(idx), Some(Some(_))) {
+                *cell = self.cell(idx);
+            }
+        }
+    }
+}
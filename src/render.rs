@@ -0,0 +1,485 @@
+use crate::Cell;
+use crossterm::{
+    cursor, queue,
+    style::{Color, Print, SetForegroundColor},
+    terminal::{BeginSynchronizedUpdate, Clear, ClearType, EndSynchronizedUpdate},
+};
+use std::{
+    io::{Result, Write},
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    thread::JoinHandle,
+};
+
+/// Ways of getting the cursor from one cell to another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Move {
+    Stay,
+    To,
+    Right(usize),
+    Left(usize),
+    Up(usize),
+    Down(usize),
+    Column,
+    NextLine(usize),
+    CarriageReturn,
+    /// Print the unchanged cells between the cursor and the target again
+    Reprint,
+}
+
+/// Writes screen buffers to the terminal, only sending the cells that changed since the last frame.
+/// Keeps track of where the cursor is and which color is set so it can leave out escape codes the
+/// terminal doesn't need, which adds up over slow links like SSH.
+#[derive(Debug)]
+pub struct Renderer {
+    /// What the terminal is showing right now
+    previous: Vec<Cell>,
+    /// Indices of the cells that changed this frame, kept around to reuse the allocation
+    dirty: Vec<usize>,
+    /// Width of the screen in cells
+    width: usize,
+    /// Terminal columns taken up by a single cell
+    char_width: usize,
+    /// Terminal column and row of the cursor, `None` when it isn't known
+    cursor: Option<(usize, usize)>,
+    /// Foreground color the terminal is set to, `None` when it isn't known
+    color: Option<Color>,
+    /// Wrap frames in synchronized update sequences so the terminal shows them all at once
+    sync: bool,
+    /// Number of changed cells above which the whole screen is redrawn
+    redraw_after: usize,
+}
+
+impl Renderer {
+    pub fn new(width: usize, height: usize, char_width: usize) -> Self {
+        Self {
+            previous: vec![Cell::default(); width * height],
+            dirty: Vec::with_capacity(width * height),
+            width,
+            char_width,
+            cursor: None,
+            color: None,
+            sync: true,
+            redraw_after: width * height / 2,
+        }
+    }
+
+    /// Sets whether frames are wrapped in synchronized update sequences (DEC mode 2026).
+    /// Terminals that don't know the mode ignore it, but it can be turned off for those that don't.
+    pub fn sync(mut self, sync: bool) -> Self {
+        self.sync = sync;
+        self
+    }
+
+    /// Sets the fraction of the screen that has to change before the whole screen is redrawn
+    /// instead of just the changed cells. A threshold of 1.0 never redraws the whole screen.
+    pub fn redraw_threshold(mut self, threshold: f32) -> Self {
+        self.redraw_after = (self.previous.len() as f32 * threshold) as usize;
+        self
+    }
+
+    /// Draws the difference between `cells` and the last frame and returns how many cells it wrote.
+    /// Nothing is written when nothing changed.
+    pub fn draw(&mut self, cells: &[Cell], w: &mut impl Write) -> Result<usize> {
+        let mut redraw_screen = false;
+
+        for (i, (a, b)) in cells.iter().zip(&self.previous).enumerate() {
+            if a != b {
+                self.dirty.push(i);
+            }
+            if self.dirty.len() > self.redraw_after {
+                redraw_screen = true;
+                break;
+            }
+        }
+        if self.dirty.is_empty() {
+            return Ok(0);
+        }
+
+        if self.sync {
+            queue!(w, BeginSynchronizedUpdate)?;
+        }
+        let written = if redraw_screen {
+            self.redraw(cells, w)?;
+            cells.len()
+        } else {
+            let written = self.dirty.len();
+            self.draw_dirty(cells, w)?;
+            written
+        };
+        if self.sync {
+            queue!(w, EndSynchronizedUpdate)?;
+        }
+
+        Ok(written)
+    }
+
+    /// Redraws every cell, starting each row with its own cursor move so rows line up even when
+    /// the terminal width isn't a multiple of the character width.
+    fn redraw(&mut self, cells: &[Cell], w: &mut impl Write) -> Result<()> {
+        for (y, chunk) in cells.chunks(self.width).enumerate() {
+            queue!(w, cursor::MoveTo(0, y as u16))?;
+            self.cursor = Some((0, y));
+            for cell in chunk {
+                self.put(cell, w)?;
+            }
+        }
+
+        self.previous.copy_from_slice(cells);
+        self.dirty.clear();
+        Ok(())
+    }
+
+    fn draw_dirty(&mut self, cells: &[Cell], w: &mut impl Write) -> Result<()> {
+        let mut dirty = std::mem::take(&mut self.dirty);
+        // Cells sharing a color are drawn together so the color only has to be set once.
+        dirty.sort_unstable_by_key(|idx| (color_key(&cells[*idx]), *idx));
+        for &idx in &dirty {
+            self.move_to(cells, idx, w)?;
+            self.put(&cells[idx], w)?;
+            self.previous[idx] = cells[idx];
+        }
+        dirty.clear();
+        self.dirty = dirty;
+
+        Ok(())
+    }
+
+    /// Writes a cell at the cursor, only setting the color when it changes.
+    /// Blank cells don't show their color so they never set it.
+    #[inline(always)]
+    fn put(&mut self, cell: &Cell, w: &mut impl Write) -> Result<()> {
+        if cell.is_visible() {
+            if self.color != Some(cell.color) {
+                queue!(w, SetForegroundColor(cell.color))?;
+                self.color = Some(cell.color);
+            }
+            queue!(w, Print(cell.char))?;
+        } else {
+            for _ in 0..self.char_width {
+                queue!(w, Print(' '))?;
+            }
+        }
+
+        // Relative moves from the last column depend on how the terminal handles wrapping.
+        self.cursor = self
+            .cursor
+            .map(|(x, y)| (x + self.char_width, y))
+            .filter(|(x, _)| *x < self.width * self.char_width);
+        Ok(())
+    }
+
+    /// Moves the cursor to the cell at `idx` with as few bytes as it can.
+    fn move_to(&mut self, cells: &[Cell], idx: usize, w: &mut impl Write) -> Result<()> {
+        let x = (idx % self.width) * self.char_width;
+        let y = idx / self.width;
+
+        match self.cheapest_move(cells, idx) {
+            Move::Stay => {}
+            Move::To => queue!(w, cursor::MoveTo(x as u16, y as u16))?,
+            Move::Right(n) => queue!(w, cursor::MoveRight(n as u16))?,
+            Move::Left(n) => queue!(w, cursor::MoveLeft(n as u16))?,
+            Move::Up(n) => queue!(w, cursor::MoveUp(n as u16))?,
+            Move::Down(n) => queue!(w, cursor::MoveDown(n as u16))?,
+            Move::Column => queue!(w, cursor::MoveToColumn(x as u16))?,
+            Move::NextLine(n) => queue!(w, cursor::MoveToNextLine(n as u16))?,
+            Move::CarriageReturn => queue!(w, Print('\r'))?,
+            Move::Reprint => {
+                let (cursor_x, _) = self.cursor.unwrap_or_default();
+                for cell in &cells[y * self.width + cursor_x / self.char_width..idx] {
+                    self.put(cell, w)?;
+                }
+            }
+        }
+        self.cursor = Some((x, y));
+        Ok(())
+    }
+
+    fn cheapest_move(&self, cells: &[Cell], idx: usize) -> Move {
+        let x = (idx % self.width) * self.char_width;
+        let y = idx / self.width;
+        // ESC [ row ; column H
+        let absolute = (Move::To, 4 + digits(y + 1) + digits(x + 1));
+        let Some((cursor_x, cursor_y)) = self.cursor else {
+            return absolute.0;
+        };
+        if (cursor_x, cursor_y) == (x, y) {
+            return Move::Stay;
+        }
+
+        // ESC [ n letter
+        let relative = |n: usize| 3 + digits(n);
+        let mut options = [None; 4];
+        if cursor_y == y {
+            options[0] = Some((Move::Column, relative(x + 1)));
+            if x == 0 {
+                options[1] = Some((Move::CarriageReturn, 1));
+            } else if x > cursor_x {
+                options[1] = Some((Move::Right(x - cursor_x), relative(x - cursor_x)));
+                options[2] = self
+                    .reprint_cost(cells, y * self.width + cursor_x / self.char_width, idx)
+                    .map(|cost| (Move::Reprint, cost));
+            } else {
+                options[1] = Some((Move::Left(cursor_x - x), relative(cursor_x - x)));
+            }
+        } else if cursor_x == x && y > cursor_y {
+            options[0] = Some((Move::Down(y - cursor_y), relative(y - cursor_y)));
+        } else if cursor_x == x {
+            options[0] = Some((Move::Up(cursor_y - y), relative(cursor_y - y)));
+        } else if x == 0 && y > cursor_y {
+            options[0] = Some((Move::NextLine(y - cursor_y), relative(y - cursor_y)));
+        }
+
+        options
+            .into_iter()
+            .flatten()
+            .fold(
+                absolute,
+                |best, option| {
+                    if option.1 < best.1 { option } else { best }
+                },
+            )
+            .0
+    }
+
+    /// Bytes it takes to print the unchanged cells in `from..to` again, or `None` when one of them
+    /// would need a color change.
+    fn reprint_cost(&self, cells: &[Cell], from: usize, to: usize) -> Option<usize> {
+        cells[from..to].iter().try_fold(0, |cost, cell| {
+            if !cell.is_visible() {
+                Some(cost + self.char_width)
+            } else if Some(cell.color) == self.color {
+                Some(cost + cell.char.len_utf8())
+            } else {
+                None
+            }
+        })
+    }
+}
+
+/// What the render thread has done so far.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FrameStats {
+    /// Frames written to the terminal
+    pub frames: u64,
+    /// Cells written for the last frame
+    pub cells: usize,
+}
+
+/// State shared between the main thread and the render thread.
+#[derive(Debug, Default)]
+struct Slot {
+    /// Newest frame that hasn't been drawn yet
+    frame: Option<Vec<Cell>>,
+    /// Renderer to switch to before the next frame, set when the screen changed size
+    renderer: Option<Renderer>,
+    /// Buffers the render thread is done with, handed back so frames don't allocate
+    free: Vec<Vec<Cell>>,
+    /// First error the render thread ran into, it stops drawing after that
+    error: Option<std::io::Error>,
+    /// Set while the render thread is writing to the terminal
+    busy: bool,
+    /// Set once the render thread is gone, it won't get to anything handed to it after that
+    exited: bool,
+    stats: FrameStats,
+    stop: bool,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    slot: Mutex<Slot>,
+    changed: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, Slot> {
+        self.slot.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// Draws frames on a thread of its own so a slow terminal doesn't hold up input or the rain.
+/// Only the newest frame is kept: when the terminal can't keep up, frames it didn't get to are
+/// dropped instead of piling up.
+#[derive(Debug)]
+pub struct RenderThread {
+    shared: Arc<Shared>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl RenderThread {
+    pub fn spawn<W: Write + Send + 'static>(renderer: Renderer, writer: W) -> Self {
+        let shared = Arc::new(Shared::default());
+        // The frame being drawn, the one waiting and the one being filled in.
+        let cells = renderer.previous.len();
+        shared.lock().free = (0..3).map(|_| Vec::with_capacity(cells)).collect();
+        let handle = std::thread::Builder::new()
+            .name("render".into())
+            .spawn({
+                let shared = Arc::clone(&shared);
+                move || {
+                    let _exit = ExitGuard(&shared);
+                    render_loop(&shared, renderer, writer)
+                }
+            })
+            .expect("failed to spawn render thread");
+
+        Self {
+            shared,
+            handle: Some(handle),
+        }
+    }
+
+    /// Hands a copy of `cells` to the render thread, replacing the last frame if it wasn't drawn yet.
+    /// Returns the error the render thread stopped on, if any.
+    pub fn submit(&self, cells: &[Cell]) -> Result<()> {
+        let mut buffer = {
+            let mut slot = self.shared.lock();
+            if let Some(err) = slot.error.take() {
+                return Err(err);
+            }
+            slot.free.pop().unwrap_or_default()
+        };
+        buffer.clear();
+        buffer.extend_from_slice(cells);
+
+        let mut slot = self.shared.lock();
+        if let Some(stale) = slot.frame.replace(buffer) {
+            slot.free.push(stale);
+        }
+        self.shared.changed.notify_all();
+        Ok(())
+    }
+
+    /// Waits until the render thread has written everything it was given, so the terminal can be
+    /// used from this thread. Returns the error the render thread stopped on, if any.
+    pub fn sync(&self) -> Result<()> {
+        let mut slot = self.shared.lock();
+        while (slot.frame.is_some() || slot.renderer.is_some() || slot.busy)
+            && slot.error.is_none()
+            && !slot.exited
+        {
+            slot = self
+                .shared
+                .changed
+                .wait(slot)
+                .unwrap_or_else(|err| err.into_inner());
+        }
+        match slot.error.take() {
+            Some(err) => Err(err),
+            None if slot.exited => Err(std::io::Error::other("render thread stopped")),
+            None => Ok(()),
+        }
+    }
+
+    /// Frames drawn so far and how many cells the last one took.
+    pub fn stats(&self) -> FrameStats {
+        self.shared.lock().stats
+    }
+
+    /// Switches to a renderer for a new screen size. The screen is cleared and any frame that
+    /// hasn't been drawn yet is thrown away, since it was made for the old size.
+    pub fn set_renderer(&self, renderer: Renderer) {
+        let mut slot = self.shared.lock();
+        if let Some(stale) = slot.frame.take() {
+            slot.free.push(stale);
+        }
+        slot.renderer = Some(renderer);
+        self.shared.changed.notify_all();
+    }
+}
+
+impl Drop for RenderThread {
+    /// Draws the last frame, if there is one waiting, and waits for the thread to finish.
+    /// When unwinding from a panic the terminal has already been restored, so the frame is dropped.
+    fn drop(&mut self) {
+        let mut slot = self.shared.lock();
+        if std::thread::panicking() {
+            slot.frame = None;
+            slot.renderer = None;
+        }
+        slot.stop = true;
+        drop(slot);
+        self.shared.changed.notify_all();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Lets anyone waiting on the render thread know that it is gone, even when it panicked in the
+/// middle of a frame and never got to clear `busy`.
+struct ExitGuard<'a>(&'a Shared);
+
+impl Drop for ExitGuard<'_> {
+    fn drop(&mut self) {
+        let mut slot = self.0.lock();
+        slot.busy = false;
+        slot.exited = true;
+        if std::thread::panicking() && slot.error.is_none() {
+            slot.error = Some(std::io::Error::other("render thread panicked"));
+        }
+        self.0.changed.notify_all();
+    }
+}
+
+fn render_loop(shared: &Shared, mut renderer: Renderer, mut w: impl Write) {
+    loop {
+        let (frame, new_renderer) = {
+            let mut slot = shared.lock();
+            while slot.frame.is_none() && slot.renderer.is_none() && !slot.stop {
+                slot = shared
+                    .changed
+                    .wait(slot)
+                    .unwrap_or_else(|err| err.into_inner());
+            }
+            if slot.frame.is_none() && slot.renderer.is_none() {
+                return;
+            }
+            slot.busy = true;
+            (slot.frame.take(), slot.renderer.take())
+        };
+
+        let result = (|| {
+            if let Some(new_renderer) = new_renderer {
+                renderer = new_renderer;
+                queue!(w, Clear(ClearType::All))?;
+            }
+            let written = match &frame {
+                Some(cells) => Some(renderer.draw(cells, &mut w)?),
+                None => None,
+            };
+            w.flush()?;
+            Ok(written)
+        })();
+
+        let mut slot = shared.lock();
+        slot.busy = false;
+        if let Some(cells) = frame {
+            slot.free.push(cells);
+        }
+        if let Ok(Some(cells)) = result {
+            slot.stats.frames += 1;
+            slot.stats.cells = cells;
+        }
+        shared.changed.notify_all();
+        if let Err(err) = result {
+            slot.error = Some(err);
+            return;
+        }
+    }
+}
+
+/// Orders cells by color with blank cells first, since they don't need a color at all.
+fn color_key(cell: &Cell) -> u32 {
+    if !cell.is_visible() {
+        return 0;
+    }
+    match cell.color {
+        Color::Rgb { r, g, b } => 1 << 24 | (r as u32) << 16 | (g as u32) << 8 | b as u32,
+        Color::AnsiValue(value) => 2 << 24 | value as u32,
+        _ => 3 << 24,
+    }
+}
+
+fn digits(n: usize) -> usize {
+    n.checked_ilog10().unwrap_or(0) as usize + 1
+}
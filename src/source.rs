@@ -0,0 +1,92 @@
+use std::{
+    collections::VecDeque,
+    io::BufRead,
+    sync::mpsc::{Receiver, sync_channel},
+};
+
+/// Text the rain characters are taken from instead of a character group.
+#[derive(Debug)]
+pub enum Source {
+    /// Text read up front, gone through over and over
+    Text { chars: Vec<char>, next: usize },
+    /// Lines coming in from a reader, read on a thread of its own
+    Stream {
+        lines: Receiver<String>,
+        /// Characters of lines received but not handed out yet
+        pending: VecDeque<char>,
+    },
+}
+
+impl Source {
+    /// Lines read ahead of the rain, the reader waits once this many are queued up
+    const BACKLOG: usize = 64;
+
+    pub fn text(text: &str) -> Self {
+        Self::Text {
+            chars: text.lines().flat_map(normalize).collect(),
+            next: 0,
+        }
+    }
+
+    /// Reads `reader` line by line as the rain goes through it.
+    pub fn stream(mut reader: impl BufRead + Send + 'static) -> Self {
+        let (sender, lines) = sync_channel(Self::BACKLOG);
+        std::thread::spawn(move || {
+            let mut line = Vec::new();
+            while reader
+                .read_until(b'\n', &mut line)
+                .is_ok_and(|read| read > 0)
+            {
+                let text: String = normalize(&String::from_utf8_lossy(&line)).collect();
+                line.clear();
+                if !text.is_empty() && sender.send(text).is_err() {
+                    break;
+                }
+            }
+        });
+        Self::Stream {
+            lines,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Puts up to `count` characters that come next in `into` in place of what it held, fewer
+    /// when a stream has no more yet. `into` is left as it is when there are none at all.
+    pub fn take(&mut self, count: usize, into: &mut Vec<char>) {
+        match self {
+            Self::Text { chars, next } => {
+                if chars.is_empty() {
+                    return;
+                }
+                into.clear();
+                into.extend(chars.iter().cycle().skip(*next).take(count));
+                *next = (*next + count) % chars.len();
+            }
+            Self::Stream { lines, pending } => {
+                while pending.len() < count {
+                    // Nothing more until the reader catches up, or ever once it is done.
+                    let Ok(line) = lines.try_recv() else {
+                        break;
+                    };
+                    pending.extend(line.chars());
+                }
+                if pending.is_empty() {
+                    return;
+                }
+                let count = count.min(pending.len());
+                into.clear();
+                into.extend(pending.drain(..count));
+            }
+        }
+    }
+}
+
+/// Characters of a line as the rain shows them: runs of whitespace become a single space and
+/// control characters are left out. A space at the end keeps it apart from the next line.
+fn normalize(line: &str) -> impl Iterator<Item = char> + '_ {
+    line.split_whitespace().flat_map(|word| {
+        word.chars()
+            .filter(|c| !c.is_control())
+            .chain(std::iter::once(' '))
+    })
+}
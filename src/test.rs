@@ -1,13 +1,52 @@
-use crate::{Clock, cli::Grouping};
+use crate::{
+    Clock,
+    cli::Grouping,
+    render::{RenderThread, Renderer},
+};
 
 use super::{Parser, Rain, cli::Cli};
 use ezemoji::CharGroup;
 use pretty_assertions::assert_eq;
 use std::{
+    alloc::{GlobalAlloc, Layout, System},
     fmt::Write,
     time::{Duration, Instant},
 };
 
+/// Counts heap allocations made by the current thread so tests can check the hot path.
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+fn allocations() -> usize {
+    ALLOCATIONS.with(|count| count.get())
+}
+
+fn count_allocation() {
+    let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count_allocation();
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count_allocation();
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
 #[derive(Debug)]
 struct TestClock {
     now: Instant,
@@ -53,13 +92,22 @@ impl Default for SnapshotOptions {
     }
 }
 
+/// Text of a cell without the color escape codes.
+fn cell_text(cell: &super::Cell, width: usize) -> String {
+    if cell.is_visible() {
+        cell.char.to_string()
+    } else {
+        " ".repeat(width)
+    }
+}
+
 fn display<const N: usize>(id: usize, window: &mut String, rain: &Rain<N>) {
     let width = rain.width;
     let height = rain.height;
     let id_str = format!("{:02X}", id);
-    write!(
+    writeln!(
         window,
-        "{:-^width$}\n",
+        "{:-^width$}",
         id_str,
         width = width * rain.char_width + 5
     )
@@ -71,14 +119,33 @@ fn display<const N: usize>(id: usize, window: &mut String, rain: &Rain<N>) {
             "{}|",
             &chunk
                 .iter()
-                .map(|c| c.display(rain.char_width))
+                .map(|c| cell_text(c, rain.char_width))
                 .collect::<String>()
         )
         .unwrap();
         if i == height {
             continue;
         }
-        write!(window, "\n").unwrap();
+        writeln!(window).unwrap();
+    }
+}
+
+/// Settings the command line `args` ask for, given without the name of the program.
+fn cli_from(args: &[&str]) -> Cli {
+    Cli::parse_from(std::iter::once(env!("CARGO_PKG_NAME")).chain(args.iter().copied()))
+}
+
+/// Rain on a `width` by `height` screen, on a clock the test moves forward.
+fn new_rain(cli: &Cli, width: usize, height: usize) -> Rain<1024> {
+    Rain::<1024>::new(width, height, cli, TestClock::default())
+}
+
+/// Moves the rain on by `frames` frames `frame_time` apart, drawing each of them.
+fn run(rain: &mut Rain<1024>, frames: usize, frame_time: Duration) {
+    for _ in 0..frames {
+        rain.update();
+        rain.update_screen_buffer().unwrap();
+        rain.clock.advance(frame_time);
     }
 }
 
@@ -91,10 +158,10 @@ fn set_up_snapshot(options: SnapshotOptions) {
         group,
         direction,
     } = options;
-    let mut cli = Cli::parse();
+    let mut cli = cli_from(&[]);
     cli.group = Grouping::from(group);
     cli.direction = direction;
-    let mut rain = Rain::<1024>::new(width, height, &cli, TestClock::default());
+    let mut rain = new_rain(&cli, width, height);
     let mut window = String::new();
     for id in 0..cycles {
         rain.update();
@@ -167,7 +234,7 @@ fn test_gen_shade_color() {
 
     let colors = gen_shade_color(bc, TRUE_BLACK, length);
 
-    assert_eq!(colors.len(), length as usize);
+    assert_eq!(colors.len(), length);
     assert_eq!(colors.first(), Some(&Color::Rgb { r: 0, g: 255, b: 0 }));
     assert_eq!(colors.last(), Some(&Color::Rgb { r: 0, g: 0, b: 0 }));
 }
@@ -182,3 +249,1666 @@ fn test_random_range() {
     assert_eq!(random.random_range(0..10), 5);
     assert_eq!(random.random_range(0..10), 8);
 }
+
+#[test]
+fn test_tiny_terminal_does_not_panic() {
+    let cli = cli_from(&[]);
+    for height in 0..10 {
+        let mut rain = new_rain(&cli, 8, height);
+        run(&mut rain, 50, Duration::from_millis(100));
+        assert!(rain.windows.iter().all(|w| *w >= 1));
+    }
+}
+
+#[test]
+fn test_trail_length_bounds() {
+    use super::{Distribution, Random, TrailLength};
+    use std::str::FromStr;
+    assert_eq!(TrailLength::from_str("12"), Ok(TrailLength::Cells(12)));
+    assert_eq!(TrailLength::from_str("0.5"), Ok(TrailLength::Fraction(0.5)));
+    assert_eq!(
+        TrailLength::from_str("25%"),
+        Ok(TrailLength::Fraction(0.25))
+    );
+    assert_eq!(
+        TrailLength::from_str("100%"),
+        Ok(TrailLength::Fraction(1.0))
+    );
+    // More than the whole screen is refused either way it is written.
+    assert!(TrailLength::from_str("1.5").is_err());
+    assert!(TrailLength::from_str("150%").is_err());
+
+    let mut rng = Random::default();
+    for distribution in [
+        Distribution::Uniform,
+        Distribution::Normal,
+        Distribution::Exponential,
+    ] {
+        for _ in 0..1000 {
+            let length = distribution.sample(&mut rng, 3, 9);
+            assert!((3..=9).contains(&length), "{distribution:?} gave {length}");
+        }
+    }
+
+    let mut cli = cli_from(&[]);
+    cli.min_length = TrailLength::Fraction(0.5);
+    cli.max_length = Some(TrailLength::Fraction(0.5));
+    let rain = new_rain(&cli, 40, 20);
+    assert!(rain.windows.iter().all(|w| *w == 10));
+}
+
+#[test]
+fn test_density_limits_active_columns() {
+    let mut cli = cli_from(&[]);
+    cli.density = 0.25;
+    let mut rain = new_rain(&cli, 40, 20);
+    for _ in 0..200 {
+        run(&mut rain, 1, Duration::from_millis(100));
+        assert_eq!(rain.active.iter().filter(|a| **a).count(), 10);
+    }
+}
+
+#[test]
+fn test_gravity_speed_curve_accelerates() {
+    use super::{Speed, SpeedCurve};
+    use std::str::FromStr;
+    let mut cli = cli_from(&[]);
+    cli.speed = Speed::from_str("10").unwrap();
+    cli.speed_curve = SpeedCurve::Gravity;
+    cli.curve_strength = 4.0;
+    let mut rain = new_rain(&cli, 1, 40);
+    rain.windows[0] = 5;
+
+    let mut intervals = Vec::new();
+    for _ in 0..2000 {
+        rain.update();
+        if !rain.queue.is_empty() {
+            intervals.push(rain.time[0].interval);
+        }
+        rain.update_screen_buffer().unwrap();
+        if rain.positions[0] >= 30 {
+            break;
+        }
+        rain.clock.advance(Duration::from_millis(10));
+    }
+
+    assert!(intervals.len() >= 30);
+    assert!(intervals.windows(2).all(|w| w[0] >= w[1]));
+    assert!(intervals[0] > intervals[29] * 2);
+}
+
+#[test]
+fn test_curve_strength_is_bounded() {
+    use crate::cli::parse_curve_strength;
+    assert_eq!(parse_curve_strength("1"), Ok(1.0));
+    assert_eq!(parse_curve_strength("100"), Ok(100.0));
+    for value in ["0.5", "100.5", "1e30", "inf", "NaN"] {
+        assert!(parse_curve_strength(value).is_err(), "{value}");
+    }
+    let args = [env!("CARGO_PKG_NAME"), "--curve-strength", "1e30"];
+    assert!(Cli::try_parse_from(args).is_err());
+
+    // The strongest curve on the slowest drops still has a next step.
+    let cli = cli_from(&["--speed-curve", "gravity", "--curve-strength", "100"]);
+    let mut rain = new_rain(&cli, 4, 8);
+    run(&mut rain, 100, Duration::from_millis(100));
+
+    // The config is held to the same bounds, values out of them are left at the default.
+    let config: crate::cli::Config =
+        toml::from_str("curve_strength = nan\ndensity = 1.5\nsparse = 0.25").unwrap();
+    let mut cli = cli_from(&[]);
+    super::apply_config(&mut cli, config);
+    assert_eq!(cli.curve_strength, 3.0);
+    assert_eq!(cli.density, 1.0);
+    assert_eq!(cli.sparse, Some(0.25));
+}
+
+#[test]
+fn test_speed_parsing() {
+    use super::{Direction, Speed};
+    use std::str::FromStr;
+    assert_eq!(
+        Speed::from_str("20"),
+        Ok(Speed {
+            min: 20.0,
+            max: 20.0
+        })
+    );
+    assert_eq!(
+        Speed::from_str("5..30"),
+        Ok(Speed {
+            min: 5.0,
+            max: 30.0
+        })
+    );
+    assert_eq!(
+        Speed::from_str("5..=30"),
+        Ok(Speed {
+            min: 5.0,
+            max: 30.0
+        })
+    );
+    assert!(Speed::from_str("30..5").is_err());
+    assert!(Speed::from_str("0..5").is_err());
+    assert!(Speed::from_str("0,200").is_err());
+    assert!(Speed::from_str("").is_err());
+    // Speeds so slow or fast that the time between two steps is out of range are turned away.
+    for value in ["1e-20", "0.001", "1e300", "5..2000", "inf", "NaN"] {
+        assert!(Speed::from_str(value).is_err(), "{value}");
+    }
+    assert!(Speed::from_str("0.01..1000").is_ok());
+    assert!(Cli::try_parse_from([env!("CARGO_PKG_NAME"), "--speed", "1e-20"]).is_err());
+    let args = [env!("CARGO_PKG_NAME"), "--horizontal-speed", "1e300"];
+    assert!(Cli::try_parse_from(args).is_err());
+
+    let cli = cli_from(&["--speed", "10", "--horizontal-speed", "20"]);
+    let mut rng = super::Random::default();
+    assert_eq!(
+        cli.speed_for(Direction::Down).sample_interval(&mut rng),
+        Duration::from_millis(100)
+    );
+    assert_eq!(
+        cli.speed_for(Direction::Left).sample_interval(&mut rng),
+        Duration::from_millis(50)
+    );
+}
+
+#[test]
+fn test_column_gap_and_sparse() {
+    let mut cli = cli_from(&["--column-gap", "2", "--sparse"]);
+    let mut rain = new_rain(&cli, 40, 20);
+    let lanes = rain.lanes.clone();
+    assert!(lanes.iter().all(|lane| lane % 3 == 0));
+    assert!(lanes.len() < 14);
+
+    run(&mut rain, 100, Duration::from_millis(100));
+    for (idx, cell) in rain.screen_buffer.iter().enumerate() {
+        if cell.is_visible() {
+            assert!(lanes.contains(&(idx % rain.width)));
+        }
+    }
+
+    // Sideways rain gets a stream for every row, even on a tall terminal.
+    cli.column_gap = 0;
+    cli.sparse = None;
+    cli.direction = super::Direction::Right;
+    let rain = new_rain(&cli, 10, 30);
+    assert_eq!(rain.lanes, (0..30).collect::<Vec<_>>());
+
+    // A gap wider than any screen still leaves one stream, one that would overflow is refused.
+    let rain = new_rain(&cli_from(&["--column-gap", "1000"]), 40, 20);
+    assert_eq!(rain.lanes.len(), 1);
+    let huge = usize::MAX.to_string();
+    assert!(Cli::try_parse_from([env!("CARGO_PKG_NAME"), "--column-gap", &huge]).is_err());
+    let config: crate::cli::Config = toml::from_str("column_gap = 5000").unwrap();
+    super::apply_config(&mut cli, config);
+    assert_eq!(cli.column_gap, 0);
+}
+
+#[test]
+fn test_frame_scheduler() {
+    use super::{FrameScheduler, IDLE_POLL_INTERVAL};
+    let now = Instant::now();
+    let mut scheduler = FrameScheduler::new(10);
+
+    // Nothing drawn yet, so sleep only until the next column is due.
+    assert!(scheduler.is_frame_ready(now));
+    assert_eq!(scheduler.timeout(now, Some(now)), Duration::ZERO);
+    assert_eq!(
+        scheduler.timeout(now, Some(now + Duration::from_millis(30))),
+        Duration::from_millis(30)
+    );
+    assert_eq!(scheduler.timeout(now, None), IDLE_POLL_INTERVAL);
+
+    // Columns that are due early have to wait for the frame cap.
+    scheduler.frame_drawn(now);
+    let later = now + Duration::from_millis(20);
+    assert!(!scheduler.is_frame_ready(later));
+    assert_eq!(
+        scheduler.timeout(later, Some(later)),
+        Duration::from_millis(80)
+    );
+    assert_eq!(
+        scheduler.timeout(later, Some(now + Duration::from_millis(500))),
+        Duration::from_millis(480)
+    );
+    assert!(scheduler.is_frame_ready(now + Duration::from_millis(100)));
+
+    // The frame cap is held to the same range in the config as on the command line.
+    for (fps, expected) in [(0, 30), (1000, 1000), (1001, 30)] {
+        let config: crate::cli::Config = toml::from_str(&format!("fps = {fps}")).unwrap();
+        let mut cli = cli_from(&[]);
+        super::apply_config(&mut cli, config);
+        assert_eq!(cli.fps, expected, "{fps}");
+        let fps = fps.to_string();
+        let parsed = Cli::try_parse_from([env!("CARGO_PKG_NAME"), "--fps", &fps]);
+        assert_eq!(parsed.is_ok(), expected != 30, "{fps}");
+    }
+}
+
+#[test]
+fn test_schedule_matches_linear_scan() {
+    // Halfway through the rain changes speed, which leaves stale entries in the wheel.
+    for (speed, factor) in [("1..4", 2.0), ("5..20", 0.1)] {
+        let cli = cli_from(&["--density", "0.5", "--speed", speed]);
+        let mut rain = new_rain(&cli, 40, 20);
+        for frame in 0..500 {
+            if frame == 250 {
+                rain.change_speed(factor);
+            }
+            let now = rain.clock.now();
+            let expected: Vec<usize> = (0..rain.lanes.len())
+                .filter(|i| rain.active[*i] && now >= rain.time[*i].next_due())
+                .collect();
+            rain.update();
+            assert_eq!(rain.queue, expected, "speed {speed} frame {frame}");
+            rain.update_screen_buffer().unwrap();
+            rain.clock.advance(Duration::from_millis(20));
+        }
+    }
+}
+
+#[test]
+fn test_schedule_after_weeks_of_uptime() {
+    // Past 2^32 milliseconds, about 49.7 days, a tick no longer fits in a u32.
+    let epoch = Instant::now();
+    let later = epoch + Duration::from_secs(50 * 24 * 60 * 60);
+    let mut wheel = super::TimerWheel::new(epoch, 1);
+    wheel.expire(later, &mut Vec::new());
+    wheel.insert(later + Duration::from_millis(5), 0);
+    assert_eq!(wheel.next_due(), Some(later + Duration::from_millis(6)));
+}
+
+/// Compares `Rain::update` against scanning every column each frame on a 4K sized terminal.
+/// Run with `cargo test --release bench_update -- --ignored --nocapture`
+#[test]
+#[ignore]
+fn bench_update_large_terminal() {
+    const FRAMES: usize = 2000;
+    const FRAME_TIME: Duration = Duration::from_micros(16_667);
+    for speed in ["5..20", "1..4"] {
+        let cli = cli_from(&["--speed", speed]);
+
+        let mut rain = new_rain(&cli, 1920, 540);
+        let mut moved = 0;
+        let mut scheduled = Duration::ZERO;
+        for _ in 0..FRAMES {
+            let start = Instant::now();
+            rain.update();
+            std::hint::black_box(rain.next_due());
+            scheduled += start.elapsed();
+            moved += rain.queue.len();
+            rain.update_screen_buffer().unwrap();
+            rain.clock.advance(FRAME_TIME);
+        }
+
+        let mut rain = new_rain(&cli, 1920, 540);
+        let mut linear = Duration::ZERO;
+        for _ in 0..FRAMES {
+            let start = Instant::now();
+            let now = rain.clock.now();
+            for i in 0..rain.lanes.len() {
+                if rain.active[i] && now >= rain.time[i].next_due() {
+                    rain.queue.push(i);
+                    let interval = rain.step_interval(i);
+                    rain.time[i].last = now;
+                    rain.time[i].interval = interval;
+                }
+            }
+            // Finding the next column to wake up for took a second pass over them.
+            let next_due = (0..rain.lanes.len())
+                .filter(|i| rain.active[*i])
+                .map(|i| rain.time[i].next_due())
+                .min();
+            std::hint::black_box(next_due);
+            linear += start.elapsed();
+            rain.update_screen_buffer().unwrap();
+            rain.clock.advance(FRAME_TIME);
+        }
+
+        println!(
+            "speed {speed:>5}: {} columns, {:.1} moved per frame, scheduled {:?}/frame, linear scan {:?}/frame",
+            rain.lanes.len(),
+            moved as f64 / FRAMES as f64,
+            scheduled / FRAMES as u32,
+            linear / FRAMES as u32,
+        );
+    }
+}
+
+#[test]
+fn test_steady_state_frames_do_not_allocate() {
+    for args in [
+        vec![],
+        vec!["--shade", "--density", "0.5"],
+        vec!["--direction", "left", "-g", "moon"],
+    ] {
+        let cli = cli_from(&args);
+        let mut rain = new_rain(&cli, 80, 30);
+        let mut renderer = Renderer::new(rain.width, rain.height, rain.char_width);
+        let render_thread = RenderThread::spawn(
+            Renderer::new(rain.width, rain.height, rain.char_width),
+            std::io::sink(),
+        );
+        let mut out: Vec<u8> = Vec::with_capacity(1 << 20);
+        let mut frame = |rain: &mut Rain<1024>, out: &mut Vec<u8>| {
+            out.clear();
+            rain.update();
+            rain.update_screen_buffer().unwrap();
+            renderer.draw(&rain.screen_buffer, out).unwrap();
+            render_thread.submit(&rain.screen_buffer).unwrap();
+            rain.clock.advance(Duration::from_millis(16));
+        };
+
+        for _ in 0..2000 {
+            frame(&mut rain, &mut out);
+        }
+        let before = allocations();
+        for _ in 0..500 {
+            frame(&mut rain, &mut out);
+        }
+        assert_eq!(allocations() - before, 0, "{args:?}");
+    }
+}
+
+/// Just enough of a terminal to replay what `Renderer` writes.
+struct VirtualTerminal {
+    /// Columns of the terminal, may be more than `width * char_width`
+    columns: usize,
+    width: usize,
+    char_width: usize,
+    cells: Vec<super::Cell>,
+    cursor: (usize, usize),
+    color: super::Color,
+}
+
+impl VirtualTerminal {
+    fn new(width: usize, height: usize, char_width: usize) -> Self {
+        Self {
+            columns: width * char_width,
+            width,
+            char_width,
+            cells: vec![super::Cell::default(); width * height],
+            cursor: (0, 0),
+            color: super::Color::Reset,
+        }
+    }
+
+    fn feed(&mut self, bytes: &[u8]) {
+        let text = std::str::from_utf8(bytes).unwrap();
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\x1b' => {
+                    assert_eq!(chars.next(), Some('['));
+                    let mut params = String::new();
+                    let command = loop {
+                        let c = chars.next().unwrap();
+                        if c.is_ascii_alphabetic() {
+                            break c;
+                        }
+                        params.push(c);
+                    };
+                    self.csi(&params, command);
+                }
+                '\r' => self.cursor.0 = 0,
+                c => {
+                    let (x, y) = self.cursor;
+                    let idx = y * self.width + x / self.char_width;
+                    let in_grid = x < self.width * self.char_width && x % self.char_width == 0;
+                    if in_grid && idx < self.cells.len() {
+                        if c == ' ' {
+                            self.cells[idx] = super::Cell::default();
+                        } else {
+                            self.cells[idx] = super::Cell::new(c).color(self.color);
+                        }
+                    }
+                    // Double width blanks are written one space at a time.
+                    self.cursor.0 += if c == ' ' { 1 } else { self.char_width };
+                    if self.cursor.0 >= self.columns {
+                        self.cursor = (0, y + 1);
+                    }
+                }
+            }
+        }
+    }
+
+    fn csi(&mut self, params: &str, command: char) {
+        let nums: Vec<usize> = params
+            .split(';')
+            .filter_map(|n| n.trim_start_matches('?').parse().ok())
+            .collect();
+        let n = nums.first().copied().unwrap_or(1);
+        let (x, y) = self.cursor;
+        match command {
+            'H' => self.cursor = (nums[1] - 1, nums[0] - 1),
+            'A' => self.cursor = (x, y - n),
+            'B' => self.cursor = (x, y + n),
+            'C' => self.cursor = (x + n, y),
+            'D' => self.cursor = (x - n, y),
+            'E' => self.cursor = (0, y + n),
+            'G' => self.cursor = (n - 1, y),
+            'm' if nums == [39] => self.color = super::Color::Reset,
+            'm' => {
+                assert_eq!(&nums[..2], &[38, 2], "{params}");
+                self.color = super::Color::Rgb {
+                    r: nums[2] as u8,
+                    g: nums[3] as u8,
+                    b: nums[4] as u8,
+                };
+            }
+            _ => {}
+        }
+    }
+}
+
+#[test]
+fn test_renderer_output_matches_screen_buffer() {
+    for args in [
+        vec!["--shade"],
+        vec!["-d", "right", "-g", "moon"],
+        vec!["--column-gap", "1", "--speed", "30"],
+    ] {
+        let cli = cli_from(&args);
+        let mut rain = new_rain(&cli, 60, 20);
+        let mut renderer = Renderer::new(rain.width, rain.height, rain.char_width).sync(false);
+        let mut terminal = VirtualTerminal::new(rain.width, rain.height, rain.char_width);
+        let mut optimized = 0;
+        let mut naive = 0;
+        let mut previous = rain.screen_buffer.clone();
+        for frame in 0..300 {
+            rain.update();
+            rain.update_screen_buffer().unwrap();
+
+            let mut out = Vec::new();
+            renderer.draw(&rain.screen_buffer, &mut out).unwrap();
+            terminal.feed(&out);
+            assert_eq!(terminal.cells, rain.screen_buffer, "{args:?} frame {frame}");
+            optimized += out.len();
+
+            // What a move and color change for every changed cell costs.
+            for (idx, (cell, old)) in rain.screen_buffer.iter().zip(&previous).enumerate() {
+                if cell != old {
+                    let x = idx % rain.width * rain.char_width;
+                    let y = idx / rain.width;
+                    let mut out = Vec::new();
+                    crossterm::queue!(
+                        out,
+                        crossterm::cursor::MoveTo(x as u16, y as u16),
+                        crossterm::style::SetForegroundColor(cell.color),
+                        crossterm::style::Print(cell_text(cell, rain.char_width))
+                    )
+                    .unwrap();
+                    naive += out.len();
+                }
+            }
+            previous.clone_from(&rain.screen_buffer);
+
+            rain.clock.advance(Duration::from_millis(50));
+        }
+        assert!(
+            optimized * 10 < naive * 6,
+            "{args:?} optimized {optimized} bytes, naive {naive} bytes"
+        );
+    }
+}
+
+#[test]
+fn test_renderer_sync_and_full_redraw() {
+    use super::Cell;
+    let (width, height, char_width) = (15, 4, 2);
+    let mut renderer = Renderer::new(width, height, char_width);
+    let mut cells = vec![Cell::default(); width * height];
+
+    let mut out = Vec::new();
+    renderer.draw(&cells, &mut out).unwrap();
+    assert!(out.is_empty(), "nothing changed so nothing is written");
+
+    // Every cell changes, so the whole screen is redrawn row by row.
+    for (i, cell) in cells.iter_mut().enumerate() {
+        *cell = Cell::new(char::from(b'a' + (i % 26) as u8));
+    }
+    renderer.draw(&cells, &mut out).unwrap();
+    assert!(out.starts_with(b"\x1b[?2026h"));
+    assert!(out.ends_with(b"\x1b[?2026l"));
+
+    // A terminal one column wider than the grid doesn't wrap where the grid does.
+    let mut terminal = VirtualTerminal::new(width, height, char_width);
+    terminal.columns += 1;
+    terminal.feed(&out);
+    assert_eq!(terminal.cells, cells);
+
+    // With a threshold of 1.0 the same change is drawn cell by cell.
+    let mut renderer = Renderer::new(width, height, char_width)
+        .sync(false)
+        .redraw_threshold(1.0);
+    let mut out = Vec::new();
+    renderer.draw(&cells, &mut out).unwrap();
+    assert!(!out.starts_with(b"\x1b[?2026h"));
+    let mut terminal = VirtualTerminal::new(width, height, char_width);
+    terminal.columns += 1;
+    terminal.feed(&out);
+    assert_eq!(terminal.cells, cells);
+}
+
+/// Terminal output that takes a while to flush, like a slow SSH connection.
+#[derive(Debug, Clone, Default)]
+struct SlowOutput {
+    bytes: std::sync::Arc<std::sync::Mutex<Vec<u8>>>,
+    flushes: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+}
+
+impl std::io::Write for SlowOutput {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.bytes.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        std::thread::sleep(Duration::from_millis(10));
+        self.flushes
+            .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_render_thread_drops_stale_frames() {
+    let cli = cli_from(&[]);
+    let mut rain = new_rain(&cli, 60, 20);
+    let output = SlowOutput::default();
+    let render_thread = RenderThread::spawn(
+        Renderer::new(rain.width, rain.height, rain.char_width),
+        output.clone(),
+    );
+
+    let frames = 50;
+    for _ in 0..frames {
+        rain.update();
+        rain.update_screen_buffer().unwrap();
+        render_thread.submit(&rain.screen_buffer).unwrap();
+        rain.clock.advance(Duration::from_millis(50));
+    }
+    render_thread.sync().unwrap();
+
+    let flushes = output.flushes.load(std::sync::atomic::Ordering::SeqCst);
+    assert!(flushes < frames, "{flushes} frames drawn out of {frames}");
+    let mut terminal = VirtualTerminal::new(rain.width, rain.height, rain.char_width);
+    terminal.feed(&output.bytes.lock().unwrap());
+    assert_eq!(terminal.cells, rain.screen_buffer);
+}
+
+/// Terminal output that panics part way through the first frame.
+struct PanickingOutput;
+
+impl std::io::Write for PanickingOutput {
+    fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+        panic!("write failed");
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        panic!("flush failed");
+    }
+}
+
+#[test]
+fn test_render_thread_panic_ends_sync() {
+    let cli = cli_from(&[]);
+    let mut rain = new_rain(&cli, 20, 10);
+    let render_thread = RenderThread::spawn(
+        Renderer::new(rain.width, rain.height, rain.char_width),
+        PanickingOutput,
+    );
+    rain.update();
+    rain.update_screen_buffer().unwrap();
+    render_thread.submit(&rain.screen_buffer).unwrap();
+
+    assert!(render_thread.sync().is_err());
+    render_thread.submit(&rain.screen_buffer).ok();
+    assert!(render_thread.sync().is_err());
+}
+
+#[test]
+fn test_resume_sets_up_terminal_before_drawing() {
+    let cli = cli_from(&[]);
+    let mut rain = new_rain(&cli, 60, 20);
+    run(&mut rain, 100, Duration::from_millis(50));
+    let output = SlowOutput::default();
+    let render_thread = RenderThread::spawn(
+        Renderer::new(rain.width, rain.height, rain.char_width),
+        output.clone(),
+    );
+    let mut overlays = super::Overlays::new(&cli, rain.clock.now());
+
+    // The terminal and the render thread write to the same place.
+    super::App::resume_to(
+        &mut output.clone(),
+        &rain,
+        &cli,
+        &mut overlays,
+        None,
+        &render_thread,
+    )
+    .unwrap();
+    render_thread.sync().unwrap();
+
+    let bytes = output.bytes.lock().unwrap();
+    let setup = b"\x1b[?1049h\x1b[?25l";
+    assert!(
+        bytes.starts_with(setup),
+        "{:?}",
+        String::from_utf8_lossy(&bytes)
+    );
+    // The whole screen is drawn again, not just what changed since the last frame.
+    let mut terminal = VirtualTerminal::new(rain.width, rain.height, rain.char_width);
+    terminal.feed(&bytes[setup.len()..]);
+    assert_eq!(terminal.cells, rain.screen_buffer);
+}
+
+#[test]
+fn test_pause_and_step() {
+    let cli = cli_from(&[]);
+    let mut rain = new_rain(&cli, 40, 20);
+    let frame_time = Duration::from_millis(50);
+    run(&mut rain, 60, frame_time);
+
+    rain.toggle_pause();
+    let paused_at = rain.clock.now();
+    // Catches up on whatever was already due when it was paused.
+    run(&mut rain, 1, frame_time);
+    let positions = rain.positions.clone();
+    let screen = rain.screen_buffer.clone();
+    run(&mut rain, 60, frame_time);
+    assert_eq!(rain.clock.now(), paused_at);
+    assert_eq!(rain.positions, positions);
+    assert_eq!(rain.screen_buffer, screen);
+
+    // A step moves the columns that are due next by a single cell.
+    rain.step();
+    assert!(!rain.queue.is_empty());
+    let stepped = rain.queue.clone();
+    rain.update_screen_buffer().unwrap();
+    for (i, (after, before)) in rain.positions.iter().zip(&positions).enumerate() {
+        if stepped.contains(&i) {
+            assert!(*after == before + 1 || *after == 0, "column {i}");
+        } else {
+            assert_eq!(after, before, "column {i}");
+        }
+    }
+
+    // Time carries on from where it stopped instead of catching up on the pause.
+    rain.toggle_pause();
+    let resumed_at = rain.clock.now();
+    rain.clock.advance(Duration::from_millis(50));
+    assert_eq!(rain.clock.now(), resumed_at + Duration::from_millis(50));
+}
+
+#[test]
+fn test_redraw_while_paused() {
+    let cli = cli_from(&[]);
+    let mut rain = new_rain(&cli, 40, 20);
+    run(&mut rain, 100, Duration::from_millis(50));
+    rain.toggle_pause();
+    let frame = rain.screen_buffer.clone();
+    let positions = rain.positions.clone();
+
+    // Laying the same screen out again clears it, nothing moves to fill it back in.
+    rain.resize(40, 20, &cli);
+    assert!(
+        rain.screen_buffer
+            .iter()
+            .all(|cell| *cell == super::Cell::default())
+    );
+    rain.redraw().unwrap();
+    assert_eq!(rain.screen_buffer, frame);
+    assert_eq!(rain.positions, positions);
+}
+
+#[test]
+fn test_live_changes_keep_the_rain_going() {
+    use std::str::FromStr;
+    let mut cli = cli_from(&["--density", "0.5"]);
+    let mut rain = new_rain(&cli, 60, 20);
+    let frames = |rain: &mut Rain<1024>, count: usize| {
+        run(rain, count, Duration::from_millis(50));
+        assert_eq!(rain.screen_buffer.len(), rain.width * rain.height);
+        assert_eq!(rain.positions.len(), rain.lanes.len());
+        let active = rain.active.iter().filter(|a| **a).count();
+        assert_eq!(active, (rain.lanes.len() as f32 * 0.5).round() as usize);
+    };
+    frames(&mut rain, 100);
+
+    // Growing the screen keeps the streams that were already falling.
+    let positions = rain.positions.clone();
+    rain.resize(80, 30, &cli);
+    assert_eq!(rain.positions[..positions.len()], positions[..]);
+    assert_eq!(rain.lanes.len(), 80);
+    frames(&mut rain, 100);
+
+    rain.resize(20, 5, &cli);
+    assert_eq!(rain.lanes.len(), 20);
+    frames(&mut rain, 100);
+
+    cli.direction = super::Direction::Right;
+    rain.set_direction(&cli);
+    assert_eq!(rain.lanes.len(), 5);
+    frames(&mut rain, 100);
+
+    cli.group = Grouping::from_str("moon").unwrap();
+    rain.set_group(&cli);
+    assert_eq!(rain.width, 10);
+    frames(&mut rain, 100);
+
+    cli.shade = true;
+    rain.set_colors(&cli);
+    assert!(rain.body_colors.iter().all(|(_, shades)| shades.is_some()));
+    frames(&mut rain, 100);
+
+    let interval = rain.time[0].base;
+    rain.change_speed(2.0);
+    let halved = rain.time[0].base.as_secs_f64() - interval.as_secs_f64() / 2.0;
+    assert!(halved.abs() < 1e-6);
+    rain.change_speed(1000.0);
+    assert_eq!(rain.speed_scale, 16.0);
+    frames(&mut rain, 100);
+}
+
+#[test]
+fn test_cycle_groups_and_colors() {
+    use std::str::FromStr;
+    let mut group = Grouping::from_str("bin").unwrap();
+    let mut names = Vec::new();
+    loop {
+        group = group.next();
+        let name = format!("{:?}", group.name());
+        if names.contains(&name) {
+            break;
+        }
+        names.push(name);
+    }
+    assert_eq!(names.len(), 16);
+    assert_eq!(names.first().unwrap(), "Custom(\"Classic\")");
+    assert_eq!(names.last().unwrap(), "Bin");
+
+    assert_eq!(super::cli::next_color("green"), "red");
+    assert_eq!(super::cli::next_color("white"), "green");
+    assert_eq!(super::cli::next_color("#ff00ff"), "green");
+}
+
+#[test]
+fn test_key_bindings() {
+    use crate::keys::{Action, KeyMap, Keys};
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+    let key = |code, modifiers| KeyEvent::new(code, modifiers);
+
+    let mut keys = KeyMap::default();
+    assert_eq!(
+        keys.action(&key(KeyCode::Char('q'), KeyModifiers::NONE)),
+        Some(Action::Quit)
+    );
+    assert_eq!(
+        keys.action(&key(KeyCode::Char('Q'), KeyModifiers::SHIFT)),
+        Some(Action::Quit)
+    );
+    assert_eq!(
+        keys.action(&key(KeyCode::Char('q'), KeyModifiers::CONTROL)),
+        None
+    );
+    assert_eq!(
+        keys.action(&key(KeyCode::Char('c'), KeyModifiers::CONTROL)),
+        Some(Action::Quit)
+    );
+    assert_eq!(
+        keys.action(&key(KeyCode::Char('+'), KeyModifiers::SHIFT)),
+        Some(Action::SpeedUp)
+    );
+
+    let config: std::collections::BTreeMap<String, Keys> = toml::from_str(
+        r#"
+        quit = ["ctrl+q", "f10"]
+        pause = "q"
+        speed-up = "ctrl++"
+        next-color = []
+        "#,
+    )
+    .unwrap();
+    keys.configure(&config);
+    assert_eq!(
+        keys.action(&key(KeyCode::Char('q'), KeyModifiers::NONE)),
+        Some(Action::Pause)
+    );
+    assert_eq!(
+        keys.action(&key(KeyCode::Char('q'), KeyModifiers::CONTROL)),
+        Some(Action::Quit)
+    );
+    assert_eq!(
+        keys.action(&key(KeyCode::F(10), KeyModifiers::NONE)),
+        Some(Action::Quit)
+    );
+    assert_eq!(keys.action(&key(KeyCode::Esc, KeyModifiers::NONE)), None);
+    assert_eq!(
+        keys.action(&key(KeyCode::Char(' '), KeyModifiers::NONE)),
+        None
+    );
+    assert_eq!(
+        keys.action(&key(KeyCode::Char('+'), KeyModifiers::NONE)),
+        None
+    );
+    assert_eq!(
+        keys.action(&key(KeyCode::Char('+'), KeyModifiers::CONTROL)),
+        Some(Action::SpeedUp)
+    );
+    assert_eq!(
+        keys.action(&key(KeyCode::Char('c'), KeyModifiers::NONE)),
+        None
+    );
+    assert_eq!(
+        keys.action(&key(KeyCode::Char('g'), KeyModifiers::NONE)),
+        Some(Action::NextGroup)
+    );
+
+    assert!(keys.bind(Action::Quit, ["hyper+q"]).is_err());
+    assert!(keys.bind(Action::Quit, ["f13"]).is_err());
+
+    // Keys are shown the way they are written in the config.
+    let shown = |keys: &KeyMap, action| {
+        keys.keys(action)
+            .map(|key| key.to_string())
+            .collect::<Vec<_>>()
+    };
+    let defaults = KeyMap::default();
+    assert_eq!(shown(&defaults, Action::Quit), ["esc", "ctrl+c", "q", "Q"]);
+    assert_eq!(shown(&defaults, Action::Pause), ["space"]);
+    assert_eq!(shown(&defaults, Action::ToggleHud), ["?", "h"]);
+    assert_eq!(shown(&keys, Action::SpeedUp), ["ctrl++"]);
+    for action in Action::ALL {
+        for key in defaults.keys(action) {
+            assert_eq!(key.to_string().parse(), Ok(key));
+        }
+    }
+}
+
+#[test]
+fn test_hud_sits_on_top_of_the_rain() {
+    use crate::{Overlays, render::FrameStats};
+    for args in [vec![], vec!["-g", "moon"]] {
+        let cli = cli_from(&args);
+        let mut rain = new_rain(&cli, 80, 30);
+        let mut renderer = Renderer::new(rain.width, rain.height, rain.char_width).sync(false);
+        let mut terminal = VirtualTerminal::new(rain.width, rain.height, rain.char_width);
+        let mut overlays = Overlays::new(&cli, rain.clock.now());
+        let mut stats = FrameStats::default();
+        for frame in 0..120 {
+            // Shown for the middle third of the frames.
+            if frame % 40 == 0 && frame > 0 {
+                overlays.hud.toggle();
+            }
+            rain.clock.advance(Duration::from_millis(50));
+            rain.update();
+            rain.update_screen_buffer().unwrap();
+
+            let cells = if overlays.is_shown() {
+                overlays.compose(&rain, &cli, stats)
+            } else {
+                &rain.screen_buffer
+            };
+            let mut out = Vec::new();
+            stats.cells = renderer.draw(cells, &mut out).unwrap();
+            stats.frames += 1;
+            terminal.feed(&out);
+            assert_eq!(terminal.cells, cells, "{args:?} frame {frame}");
+        }
+        assert!(!overlays.is_shown());
+        assert_eq!(terminal.cells, rain.screen_buffer, "{args:?}");
+
+        overlays.hud.toggle();
+        let cells = overlays.compose(&rain, &cli, stats).to_vec();
+        let row = |y: usize| {
+            cells[y * rain.width..(y + 1) * rain.width]
+                .iter()
+                .map(|cell| cell.char)
+                .collect::<String>()
+        };
+        let text = (0..rain.height).map(row).collect::<Vec<_>>().join("\n");
+        let expected = if rain.char_width == 2 {
+            "ｇｒｏｕｐ"
+        } else {
+            "group"
+        };
+        assert!(text.contains(expected), "{args:?}\n{text}");
+        // Only the top left corner is covered.
+        let covered = cells
+            .iter()
+            .zip(&rain.screen_buffer)
+            .enumerate()
+            .filter(|(_, (hud, rain))| hud != rain)
+            .map(|(idx, _)| idx)
+            .collect::<Vec<_>>();
+        assert!(
+            covered
+                .iter()
+                .all(|idx| idx % rain.width >= 1 && idx / rain.width >= 1)
+        );
+        assert!(covered.iter().all(|idx| idx % rain.width < rain.width - 1));
+    }
+}
+
+#[test]
+fn test_hud_shows_speed_set_and_scale() {
+    use crate::{Overlays, render::FrameStats};
+    let cli = cli_from(&["--horizontal-speed", "2..4"]);
+    let mut rain = new_rain(&cli, 80, 30);
+    let mut overlays = Overlays::new(&cli, rain.clock.now());
+    overlays.hud.toggle();
+    let text = |overlays: &mut Overlays, rain: &Rain<1024>| {
+        let cells = overlays.compose(rain, &cli, FrameStats::default());
+        cells.iter().map(|cell| cell.char).collect::<String>()
+    };
+    assert!(text(&mut overlays, &rain).contains("5.0..20.0 cells/s "));
+
+    rain.change_speed(2.0);
+    let text = text(&mut overlays, &rain);
+    assert!(text.contains("5.0..20.0 cells/s x2.00"), "{text}");
+    assert!(text.contains("2.0..4.0 cells/s x2.00"), "{text}");
+
+    // Labels line up by the characters in them, keys that take more than a byte included.
+    let mut cli = cli_from(&[]);
+    let config = toml::from_str(r#"pause = ["ä", "ö", "ü", "ß", "é", "è", "ñ"]"#).unwrap();
+    cli.keys.configure(&config);
+    let rain = new_rain(&cli, 80, 30);
+    let mut overlays = Overlays::new(&cli, rain.clock.now());
+    overlays.hud.toggle();
+    let cells = overlays.compose(&rain, &cli, FrameStats::default());
+    let rows: Vec<String> = cells
+        .chunks(rain.width)
+        .map(|row| row.iter().map(|cell| cell.char).collect())
+        .collect();
+    // Column the value after `label` starts at.
+    let value_x = |label: &str| {
+        let row = rows.iter().find(|row| row.contains(label)).unwrap();
+        let value = &row[row.find(label).unwrap() + label.len()..];
+        row.chars().count() - value.trim_start().chars().count()
+    };
+    assert_eq!(value_x("ä ö ü ß é è ñ"), value_x("direction"));
+}
+
+#[test]
+fn test_restore_terminal() {
+    use super::{INPUT_CAPTURE, TERMINAL_ACTIVE, restore_terminal_to};
+    use std::sync::atomic::Ordering;
+    // What the panic hook and the way out after SIGTERM or SIGHUP leave the terminal with.
+    let restore = |input_capture: bool| {
+        TERMINAL_ACTIVE.store(true, Ordering::SeqCst);
+        INPUT_CAPTURE.store(input_capture, Ordering::SeqCst);
+        let mut out = Vec::new();
+        restore_terminal_to(&mut out);
+        String::from_utf8(out).unwrap()
+    };
+
+    let out = restore(false);
+    assert_eq!(out, "\x1b[0m\x1b[?25h\x1b[?1049l");
+    // Done once, it is left alone after that.
+    let mut again = Vec::new();
+    restore_terminal_to(&mut again);
+    assert!(again.is_empty());
+
+    let out = restore(true);
+    for sequence in ["\x1b[?1000l", "\x1b[?1004l", "\x1b[?2004l"] {
+        assert!(out.contains(sequence), "{out:?}");
+    }
+    assert!(out.ends_with("\x1b[0m\x1b[?25h\x1b[?1049l"), "{out:?}");
+}
+
+#[test]
+fn test_screensaver_wakes_on_input() {
+    use crate::App;
+    use crossterm::event::{
+        Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseEvent, MouseEventKind,
+    };
+
+    let cli = cli_from(&["--screensaver"]);
+    assert_eq!(cli.screensaver, Some(0));
+    let cli = cli_from(&["--screensaver", "3"]);
+    assert_eq!(cli.screensaver, Some(3));
+
+    let key = KeyEvent::new(KeyCode::Char('a'), KeyModifiers::NONE);
+    assert!(App::is_wake_event(&Event::Key(key)));
+    let release = KeyEvent::new_with_kind(
+        KeyCode::Char('a'),
+        KeyModifiers::NONE,
+        KeyEventKind::Release,
+    );
+    assert!(!App::is_wake_event(&Event::Key(release)));
+    let mouse = MouseEvent {
+        kind: MouseEventKind::Moved,
+        column: 10,
+        row: 10,
+        modifiers: KeyModifiers::NONE,
+    };
+    assert!(App::is_wake_event(&Event::Mouse(mouse)));
+    assert!(App::is_wake_event(&Event::Paste(String::from("hello"))));
+    assert!(App::is_wake_event(&Event::FocusLost));
+    assert!(!App::is_wake_event(&Event::Resize(80, 24)));
+}
+
+#[test]
+fn test_duration_parsing() {
+    use super::cli::parse_duration;
+    assert_eq!(parse_duration("30"), Ok(Duration::from_secs(30)));
+    assert_eq!(parse_duration("30s"), Ok(Duration::from_secs(30)));
+    assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+    assert_eq!(parse_duration("2m"), Ok(Duration::from_secs(120)));
+    assert_eq!(parse_duration("1h30m"), Ok(Duration::from_secs(5400)));
+    assert_eq!(parse_duration("1.5s"), Ok(Duration::from_millis(1500)));
+    for invalid in ["", "s", "10x", "-5", "1m30", "ms5"] {
+        assert!(parse_duration(invalid).is_err(), "{invalid}");
+    }
+
+    // Up to a year, any more would overflow the deadline long before it fits in a `Duration`.
+    assert_eq!(parse_duration("8760h"), Ok(super::cli::MAX_DURATION));
+    for too_long in ["8760h1s", "1e18", "18446744073709551615s"] {
+        assert!(parse_duration(too_long).is_err(), "{too_long}");
+    }
+    let cli = cli_from(&["--duration", "8760h"]);
+    let limit = super::RunLimit::new(&cli, Instant::now());
+    assert!(!limit.is_reached(Instant::now()));
+}
+
+#[test]
+fn test_run_limit_and_drain() {
+    use super::RunLimit;
+    let cli = cli_from(&["--duration", "2s", "--drain"]);
+    let mut rain = new_rain(&cli, 40, 20);
+    let limit = RunLimit::new(&cli, rain.clock.now());
+    assert_eq!(
+        limit.timeout(rain.clock.now()),
+        Some(Duration::from_secs(2))
+    );
+
+    let mut frame = 0;
+    while !limit.is_reached(rain.clock.now()) {
+        run(&mut rain, 1, Duration::from_millis(50));
+        frame += 1;
+    }
+    assert_eq!(frame, 40);
+    assert!(rain.screen_buffer.iter().any(|cell| cell.is_visible()));
+
+    rain.drain();
+    let mut frames = 0;
+    let mut active = rain.active.clone();
+    let mut empty_since = None;
+    while !rain.is_drained() {
+        run(&mut rain, 1, Duration::from_millis(50));
+        frames += 1;
+        assert!(frames < 2000, "rain never drained");
+        // Columns that fall off the screen stay empty instead of starting over.
+        for (i, (now, before)) in rain.active.iter().zip(&active).enumerate() {
+            assert!(*before || !now, "column {i} started again");
+            if !now {
+                assert_eq!(rain.positions[i], 0, "column {i}");
+            }
+        }
+        active.clone_from(&rain.active);
+        if rain.screen_buffer.iter().any(|cell| cell.is_visible()) {
+            assert!(!rain.is_drained(), "frame {frames}");
+            empty_since = None;
+        } else {
+            empty_since.get_or_insert(frames);
+        }
+    }
+    // The run ends once the last drop has left the screen, which takes it two more steps at the
+    // slowest speed of 5 cells a second.
+    let empty_since = empty_since.expect("screen is empty once drained");
+    assert!(frames - empty_since <= 8, "{} frames", frames - empty_since);
+
+    let cli = cli_from(&["--frames", "3"]);
+    let mut limit = RunLimit::new(&cli, rain.clock.now());
+    assert_eq!(limit.timeout(rain.clock.now()), None);
+    for _ in 0..3 {
+        assert!(!limit.is_reached(rain.clock.now()));
+        limit.frame_drawn();
+    }
+    assert!(limit.is_reached(rain.clock.now()));
+    // A limit of no frames at all would still draw the first one.
+    assert!(Cli::try_parse_from([env!("CARGO_PKG_NAME"), "--frames", "0"]).is_err());
+}
+
+#[test]
+fn test_resize_while_draining() {
+    let cli = cli_from(&[]);
+    let mut rain = new_rain(&cli, 40, 20);
+    run(&mut rain, 100, Duration::from_millis(50));
+    rain.drain();
+    run(&mut rain, 5, Duration::from_millis(50));
+
+    // The streams that are still falling keep going, the new ones stay put.
+    let active = rain.active.clone();
+    rain.resize(80, 30, &cli);
+    assert_eq!(rain.active[..active.len()], active[..]);
+    assert!(rain.active[active.len()..].iter().all(|active| !active));
+    let mut frames = 0;
+    while !rain.is_drained() {
+        run(&mut rain, 1, Duration::from_millis(50));
+        frames += 1;
+        assert!(frames < 2000, "rain never drained");
+        assert!(rain.active[active.len()..].iter().all(|active| !active));
+    }
+}
+
+#[test]
+fn test_message_is_revealed_by_the_rain() {
+    let cli = cli_from(&["--message", "WAKE UP\nNEO", "--message-color", "red"]);
+    let mut rain = new_rain(&cli, 21, 9);
+    let text = |rain: &Rain<1024>| {
+        rain.screen_buffer
+            .chunks(rain.width)
+            .map(|row| row.iter().map(|cell| cell.char).collect::<String>())
+            .collect::<Vec<_>>()
+    };
+    assert!(rain.screen_buffer.iter().all(|cell| !cell.is_visible()));
+
+    // Every column passes through the message at least once, then the rain is drained so only
+    // the message is left.
+    run(&mut rain, 200, Duration::from_millis(50));
+    rain.drain();
+    while !rain.is_drained() {
+        run(&mut rain, 1, Duration::from_millis(50));
+    }
+    let mut expected = vec![" ".repeat(21); 9];
+    expected[3] = format!("{:^21}", "WAKE UP");
+    expected[4] = format!("{:^21}", "NEO");
+    assert_eq!(text(&rain), expected);
+    let red = super::Color::Rgb { r: 255, g: 0, b: 0 };
+    assert!(
+        rain.screen_buffer
+            .iter()
+            .filter(|cell| cell.is_visible())
+            .all(|cell| cell.color == red)
+    );
+
+    // The message moves to the middle of the new screen and stays revealed.
+    rain.resize(11, 4, &cli);
+    assert_eq!(
+        text(&rain),
+        ["           ", "  WAKE UP  ", "    NEO    ", "           "]
+    );
+    // Lines wider than the screen are cut off on both sides.
+    rain.resize(5, 1, &cli);
+    assert_eq!(text(&rain), ["AKE U"]);
+}
+
+#[test]
+fn test_mask_keeps_the_rain_in_its_shape() {
+    use crate::mask::Mask;
+    let shape = |mask: &Mask, width: usize, height: usize| {
+        (0..height)
+            .map(|y| {
+                (0..width)
+                    .map(|x| {
+                        if mask.is_open(y * width + x) {
+                            '#'
+                        } else {
+                            ' '
+                        }
+                    })
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // ASCII art grows as far as it fits and is centered.
+    let mut mask = Mask::from_art(" #\n# #\n", false);
+    mask.layout(12, 4, 1);
+    assert_eq!(
+        shape(&mask, 12, 4),
+        [
+            "     ##     ",
+            "     ##     ",
+            "   ##  ##   ",
+            "   ##  ##   "
+        ]
+    );
+    // Double width cells take up two columns of the shape.
+    mask.layout(6, 4, 2);
+    assert_eq!(shape(&mask, 6, 4), ["  #   ", "  #   ", " # #  ", " # #  "]);
+
+    // Text is drawn in the block font, inverted the rain falls around it.
+    let mut mask = Mask::from_text("i", true);
+    mask.layout(8, 5, 1);
+    assert_eq!(
+        shape(&mask, 8, 5),
+        ["#      #", "###  ###", "###  ###", "###  ###", "#      #"]
+    );
+    // Too big for the screen it is cut off on both sides.
+    mask.layout(4, 3, 1);
+    assert_eq!(shape(&mask, 4, 3), ["#  #", "#  #", "#  #"]);
+
+    for invert in [false, true] {
+        let mut args = vec!["--mask", "HI"];
+        if invert {
+            args.push("--invert-mask");
+        }
+        let cli = cli_from(&args);
+        let mut rain = new_rain(&cli, 40, 12);
+        let mut mask = Mask::from_text("HI", invert);
+        mask.layout(40, 12, 1);
+        let mut seen = 0;
+        for _ in 0..200 {
+            run(&mut rain, 1, Duration::from_millis(50));
+            for (idx, cell) in rain.screen_buffer.iter().enumerate() {
+                if cell.is_visible() {
+                    assert!(mask.is_open(idx), "rain outside of the mask at {idx}");
+                    seen += 1;
+                }
+            }
+        }
+        assert!(seen > 0);
+    }
+}
+
+#[test]
+fn test_image_lights_up_the_rain() {
+    use crate::image::{Image, ImageMode, Picture};
+
+    // Plain and binary PPM and PGM read the same, comments and larger maximum values included.
+    let plain = Image::decode(b"P3\n# two by one\n2 1\n255\n255 0 0  0 0 255\n").unwrap();
+    let binary = Image::decode(b"P6 2 1 65535\n\xff\xff\0\0\0\0\0\0\0\0\xff\xff").unwrap();
+    assert_eq!(plain, binary);
+    assert_eq!(plain.pixels, [[255, 0, 0], [0, 0, 255]]);
+    let gray = Image::decode(b"P5 2 1 255\n\x00\xff").unwrap();
+    assert_eq!(gray, Image::decode(b"P2 2 1 15 0 15").unwrap());
+    assert!(Image::decode(b"P6 2 2 255\n\x00").is_err());
+    assert!(Image::decode(b"GIF89a").is_err());
+
+    // Left half black, right half white, stretched over the screen.
+    let mut picture = Picture::new(gray.clone(), ImageMode::Brightness);
+    picture.layout(4, 2);
+    let green = super::Color::Rgb { r: 0, g: 200, b: 0 };
+    let tints: Vec<_> = (0..8).map(|idx| picture.tint(idx, green, false)).collect();
+    let dim = super::Color::Rgb { r: 0, g: 30, b: 0 };
+    assert_eq!(tints, [dim, dim, green, green, dim, dim, green, green]);
+
+    // Cells covering several pixels take their average.
+    picture.layout(1, 1);
+    let gray = super::Color::Rgb { r: 0, g: 115, b: 0 };
+    assert_eq!(picture.tint(0, green, false), gray);
+
+    // In color the trail takes on the picture, dimmed like the rain, and heads stay as they are.
+    let mut picture = Picture::new(plain, ImageMode::Color);
+    picture.layout(2, 1);
+    let red = super::Color::Rgb { r: 200, g: 0, b: 0 };
+    assert_eq!(picture.tint(0, green, false), red);
+    assert_eq!(picture.tint(1, green, true), green);
+
+    let cli = cli_from(&["--image", "rain.pgm"]);
+    let mut rain = new_rain(&cli, 20, 10);
+    let image = Image::decode(b"P2 2 1 1 0 1").unwrap();
+    rain.set_picture(Picture::new(image, cli.image_mode));
+    run(&mut rain, 100, Duration::from_millis(50));
+    let brightest = |columns: std::ops::Range<usize>| {
+        rain.screen_buffer
+            .chunks(rain.width)
+            .flat_map(|row| &row[columns.clone()])
+            .filter(|cell| cell.is_visible())
+            .map(|cell| match cell.color {
+                super::Color::Rgb { r, g, b } => r.max(g).max(b),
+                _ => 255,
+            })
+            .max()
+            .unwrap()
+    };
+    assert!(brightest(0..10) < 60);
+    assert!(brightest(10..20) == 255);
+}
+
+#[test]
+fn test_source_text_is_spelled_out_by_the_drops() {
+    use crate::source::Source;
+    let text = "wake up, neo\n\n\tfollow   the white\trabbit\n";
+    let spelled = "wake up, neo follow the white rabbit ";
+
+    // Whitespace is squeezed into single spaces and the text starts over once it runs out.
+    let mut taken = Vec::new();
+    let mut source = Source::text(text);
+    source.take(spelled.len() + 4, &mut taken);
+    assert_eq!(String::from_iter(&taken), format!("{spelled}wake"));
+    // Taken again in place of what was there, and kept when there is no text at all.
+    source.take(4, &mut taken);
+    assert_eq!(String::from_iter(&taken), " up,");
+    Source::text(" \n\t").take(10, &mut taken);
+    assert_eq!(String::from_iter(&taken), " up,");
+
+    // Streamed text is handed out as it comes in, and there is no more once the reader is done.
+    let mut source = Source::stream(std::io::Cursor::new(text));
+    let mut streamed = String::new();
+    while streamed.len() < spelled.len() {
+        taken.clear();
+        source.take(8, &mut taken);
+        streamed.extend(&taken);
+        std::thread::yield_now();
+    }
+    assert_eq!(streamed, spelled);
+    taken.clear();
+    source.take(8, &mut taken);
+    assert!(taken.is_empty());
+
+    // Each column reads as a part of the text from top to bottom, on a screen big enough for a
+    // lot of text to be falling at once. None of the text repeats, so a column that is made up of
+    // two parts of it can't be mistaken for a part of it.
+    let text = (0..5000)
+        .map(|n| n.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+        + " ";
+    let cycled = text.repeat(2);
+    let cli = cli_from(&["--source", "numbers.txt"]);
+    let mut rain = new_rain(&cli, 200, 60);
+    rain.set_source(Source::text(&text));
+    let mut seen = 0;
+    for _ in 0..60 {
+        run(&mut rain, 5, Duration::from_millis(50));
+        for x in 0..rain.width {
+            let column: String = (0..rain.height)
+                .map(|y| rain.screen_buffer[y * rain.width + x].char)
+                .collect();
+            let column = column.trim();
+            assert!(cycled.contains(column), "{column:?} is not in the text");
+            seen += column.len();
+        }
+    }
+    assert!(seen > 200 * 60);
+}
+
+#[test]
+fn test_redraw_keeps_the_source_text() {
+    use crate::source::Source;
+    let text = (0..5000)
+        .map(|n| n.to_string())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let cli = cli_from(&["--source", "numbers.txt"]);
+    let paused = || {
+        let mut rain = new_rain(&cli, 200, 60);
+        rain.set_source(Source::text(&text));
+        run(&mut rain, 100, Duration::from_millis(50));
+        // Until a stream has just started over, it is moved back to the top of the screen to
+        // draw it again.
+        while !(0..rain.positions.len()).any(|i| rain.active[i] && rain.positions[i] == 1) {
+            run(&mut rain, 1, Duration::from_millis(50));
+        }
+        rain.toggle_pause();
+        rain
+    };
+    let next_text = |rain: &mut Rain<1024>| {
+        let mut taken = Vec::new();
+        rain.source.as_mut().unwrap().take(100, &mut taken);
+        String::from_iter(taken)
+    };
+
+    let mut rain = paused();
+    let spelled = rain.spelled.clone();
+    let frame = rain.screen_buffer.clone();
+    rain.redraw().unwrap();
+    assert_eq!(rain.spelled, spelled);
+    assert_eq!(rain.screen_buffer, frame);
+    assert_eq!(next_text(&mut rain), next_text(&mut paused()));
+}
+
+#[test]
+fn test_intro_types_out_the_script() {
+    use crate::intro::{Intro, IntroConfig, Script};
+    let config: IntroConfig = toml::from_str(
+        r#"
+        typing = "100ms"
+        delay = "1s"
+        lines = ["Wake up, Neo...", { text = "Knock,\nknock.", delay = "2s" }]
+        "#,
+    )
+    .unwrap();
+    let script = Script::try_from(&config).unwrap();
+    let config: IntroConfig = toml::from_str(
+        r#"typing = "fast"
+        lines = []"#,
+    )
+    .unwrap();
+    assert!(Script::try_from(&config).is_err());
+
+    let mut intro = Intro::new(script, TestClock::default());
+    let color = super::Color::Rgb { r: 0, g: 255, b: 0 };
+    let screen = |intro: &mut Intro| {
+        intro
+            .frame(20, 4, 1, color)
+            .chunks(20)
+            .map(|row| row.iter().map(|cell| cell.char).collect::<String>())
+            .collect::<Vec<_>>()
+    };
+    let text = |rows: [&str; 4]| rows.map(|row| format!(" {row:<19}"));
+
+    assert_eq!(screen(&mut intro), text(["", "█", "", ""]));
+    assert_eq!(
+        intro.next_change(),
+        intro.now() + Duration::from_millis(100)
+    );
+    intro.clock.advance(Duration::from_millis(350));
+    assert_eq!(screen(&mut intro), text(["", "Wak█", "", ""]));
+    assert_eq!(intro.next_change(), intro.now() + Duration::from_millis(50));
+
+    // Typed out after 1.5s, then the cursor blinks until the line has been up for a second.
+    intro.clock.advance(Duration::from_millis(1150));
+    assert_eq!(screen(&mut intro), text(["", "Wake up, Neo...█", "", ""]));
+    assert_eq!(
+        intro.next_change(),
+        intro.now() + Duration::from_millis(500)
+    );
+    intro.clock.advance(Duration::from_millis(600));
+    assert_eq!(screen(&mut intro), text(["", "Wake up, Neo...", "", ""]));
+    assert_eq!(
+        intro.next_change(),
+        intro.now() + Duration::from_millis(400)
+    );
+
+    // The next line starts on an empty screen.
+    intro.clock.advance(Duration::from_millis(400 + 1300));
+    assert_eq!(screen(&mut intro), text(["", "Knock,", "knock.█", ""]));
+    assert!(!intro.is_done());
+    intro.clock.advance(Duration::from_millis(1999));
+    assert!(!intro.is_done());
+    intro.clock.advance(Duration::from_millis(1));
+    assert!(intro.is_done());
+    assert_eq!(intro.next_change(), intro.now());
+    assert_eq!(screen(&mut intro), text(["", "", "", ""]));
+}
+
+#[test]
+fn test_wall_clock_in_big_digits() {
+    use crate::{font, wall_clock::WallClock};
+    let pixels = |text: &str| {
+        font::bitmap(text)
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|set| if *set { '#' } else { ' ' })
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        pixels("12:07"),
+        [
+            "  # ###   ### ###",
+            "  #   # # # #   #",
+            "  # ###   # #   #",
+            "  # #   # # #   #",
+            "  # ###   ###   #",
+        ]
+    );
+
+    let white = super::Color::Rgb {
+        r: 255,
+        g: 255,
+        b: 255,
+    };
+    let mut clock = WallClock::new(white);
+    assert!(clock.set_time((12, 34, 56)));
+    assert!(!clock.set_time((12, 34, 56)));
+
+    // Centered on the screen, with the seconds left out when they don't fit.
+    let screen = |clock: &WallClock, width: usize, height: usize| {
+        let mut cells = vec![super::Cell::default(); width * height];
+        clock.draw(&mut cells, width, height, 1);
+        cells
+    };
+    let text = |cells: &[super::Cell], width: usize| {
+        cells
+            .chunks(width)
+            .map(|row| row.iter().map(|cell| cell.char).collect::<String>())
+            .collect::<Vec<_>>()
+    };
+    let doubled = |text: &str, row: usize| {
+        pixels(text)[row]
+            .chars()
+            .map(|c| if c == '#' { "██" } else { "  " })
+            .collect::<String>()
+    };
+    let cells = screen(&clock, 60, 7);
+    assert_eq!(text(&cells, 60)[0], " ".repeat(60));
+    for row in 0..5 {
+        assert_eq!(
+            text(&cells, 60)[row + 1],
+            format!(" {:^59}", doubled("12:34:56", row))
+        );
+    }
+    assert!(
+        cells
+            .iter()
+            .filter(|cell| cell.is_visible())
+            .all(|cell| cell.color == white)
+    );
+    assert_eq!(
+        text(&screen(&clock, 40, 5), 40)[4],
+        format!("{:^40}", doubled("12:34", 4))
+    );
+    assert!(screen(&clock, 20, 5).iter().all(|cell| !cell.is_visible()));
+    assert!(screen(&clock, 60, 4).iter().all(|cell| !cell.is_visible()));
+
+    // The next second only redraws the pixels of the last digit that changed.
+    let mut renderer = Renderer::new(60, 7, 1).sync(false);
+    renderer
+        .draw(&screen(&clock, 60, 7), &mut std::io::sink())
+        .unwrap();
+    clock.set_time((12, 34, 57));
+    let written = renderer
+        .draw(&screen(&clock, 60, 7), &mut std::io::sink())
+        .unwrap();
+    let changed = pixels("6")
+        .iter()
+        .zip(pixels("7"))
+        .flat_map(|(a, b)| {
+            a.chars()
+                .zip(b.chars())
+                .filter(|(a, b)| a != b)
+                .collect::<Vec<_>>()
+        })
+        .count();
+    assert_eq!(written, changed * 2);
+}
+
+#[test]
+fn test_countdown() {
+    use crate::{countdown::Countdown, font};
+    let cli = cli_from(&["--countdown", "90s", "--bell"]);
+    assert_eq!(cli.countdown, Some(Duration::from_secs(90)));
+
+    let start = TestClock::default().now();
+    let at = |millis: u64| start + Duration::from_millis(millis);
+    let white = super::Color::Rgb {
+        r: 255,
+        g: 255,
+        b: 255,
+    };
+    let mut countdown = Countdown::new(Duration::from_secs(90), white, true, start);
+    let screen = |countdown: &Countdown| {
+        let mut cells = vec![super::Cell::default(); 40 * 5];
+        countdown.draw(&mut cells, 40, 5, 1);
+        cells
+    };
+    let shows = |countdown: &Countdown, text: &str| {
+        let mut cells = vec![super::Cell::default(); 40 * 5];
+        font::draw_centered(&font::bitmap(text), &mut cells, 40, 1, white);
+        let chars = |cells: &[super::Cell]| cells.iter().map(|cell| cell.char).collect::<String>();
+        chars(&screen(countdown)) == chars(&cells)
+    };
+
+    assert!(countdown.tick(at(0)));
+    assert!(shows(&countdown, "01:30"));
+    assert_eq!(countdown.next_change(at(0)), at(1000));
+    assert!(!countdown.tick(at(500)));
+    assert_eq!(countdown.next_change(at(500)), at(1000));
+    assert!(countdown.tick(at(1000)));
+    assert!(shows(&countdown, "01:29"));
+    assert_eq!(countdown.take_speed_change(), None);
+
+    // The last fifth of the time the rain speeds up and the digits turn red.
+    countdown.tick(at(81_000));
+    assert!(shows(&countdown, "00:09"));
+    assert_eq!(countdown.take_speed_change(), Some(2.0));
+    assert_eq!(countdown.take_speed_change(), None);
+    let half_red = super::Color::Rgb {
+        r: 255,
+        g: 128,
+        b: 128,
+    };
+    assert!(
+        screen(&countdown)
+            .iter()
+            .filter(|cell| cell.is_visible())
+            .all(|cell| cell.color == half_red)
+    );
+    assert!(!countdown.take_ring());
+
+    // Once the time is up the bell rings once and the digits flash.
+    assert!(countdown.tick(at(90_000)));
+    assert!(shows(&countdown, "00:00"));
+    assert_eq!(countdown.take_speed_change(), Some(1.5));
+    assert!(countdown.take_ring());
+    assert!(!countdown.take_ring());
+    assert_eq!(countdown.next_change(at(90_200)), at(90_500));
+    assert!(countdown.tick(at(90_500)));
+    assert!(screen(&countdown).iter().all(|cell| !cell.is_visible()));
+    assert!(countdown.tick(at(91_000)));
+    assert!(shows(&countdown, "00:00"));
+    assert!(!countdown.take_ring());
+
+    let mut countdown = Countdown::new(Duration::from_secs(5400), white, false, start);
+    countdown.tick(at(0));
+    let mut cells = vec![super::Cell::default(); 60 * 5];
+    countdown.draw(&mut cells, 60, 5, 1);
+    let mut expected = vec![super::Cell::default(); 60 * 5];
+    font::draw_centered(&font::bitmap("1:30:00"), &mut expected, 60, 1, white);
+    assert_eq!(cells, expected);
+
+    // A year at most, longer timers stop there instead of running past the end of time.
+    assert!(Cli::try_parse_from([env!("CARGO_PKG_NAME"), "--countdown", "8761h"]).is_err());
+    let mut countdown = Countdown::new(Duration::MAX, white, false, start);
+    countdown.tick(at(0));
+    assert_eq!(countdown.remaining(start), super::cli::MAX_DURATION);
+    let mut cells = vec![super::Cell::default(); 80 * 5];
+    countdown.draw(&mut cells, 80, 5, 1);
+    let mut expected = vec![super::Cell::default(); 80 * 5];
+    font::draw_centered(&font::bitmap("8760:00:00"), &mut expected, 80, 1, white);
+    assert_eq!(cells, expected);
+}
+
+#[test]
+fn test_config_clock_gives_way_to_countdown() {
+    let config: crate::cli::Config = toml::from_str("clock = true").unwrap();
+    let mut cli = cli_from(&["--countdown", "90s"]);
+    super::apply_config(&mut cli, config);
+    assert!(!cli.clock);
+    assert_eq!(cli.countdown, Some(Duration::from_secs(90)));
+
+    let config: crate::cli::Config = toml::from_str("clock = true").unwrap();
+    let mut cli = cli_from(&[]);
+    super::apply_config(&mut cli, config);
+    assert!(cli.clock);
+}
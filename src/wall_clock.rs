@@ -0,0 +1,125 @@
+use crate::{Cell, font};
+use crossterm::style::Color;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Time of day in big digits in the middle of the screen, with the rain falling behind it.
+#[derive(Debug)]
+pub struct WallClock {
+    color: Color,
+    /// Hours, minutes and seconds on the clock
+    time: Option<(u32, u32, u32)>,
+    /// The time with seconds and without, for screens too narrow for the seconds
+    bitmaps: [Vec<Vec<bool>>; 2],
+}
+
+impl WallClock {
+    pub fn new(color: Color) -> Self {
+        Self {
+            color,
+            time: None,
+            bitmaps: Default::default(),
+        }
+    }
+
+    /// Sets the time on the clock and returns whether it changed.
+    pub fn set_time(&mut self, time: (u32, u32, u32)) -> bool {
+        if self.time == Some(time) {
+            return false;
+        }
+        let (hours, minutes, seconds) = time;
+        self.time = Some(time);
+        self.bitmaps = [
+            font::bitmap(&format!("{hours:02}:{minutes:02}:{seconds:02}")),
+            font::bitmap(&format!("{hours:02}:{minutes:02}")),
+        ];
+        true
+    }
+
+    /// Sets the clock to the local time and returns whether it changed.
+    pub fn tick(&mut self) -> bool {
+        self.set_time(local_time(SystemTime::now()))
+    }
+
+    /// Time until the clock has to tick again.
+    pub fn until_next_tick() -> Duration {
+        let since_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Duration::from_secs(1) - Duration::from_nanos(since_epoch.subsec_nanos() as u64)
+    }
+
+    /// Draws the clock over `cells`, leaving the seconds out when they don't fit and the whole
+    /// clock when the time doesn't fit either.
+    pub fn draw(&self, cells: &mut [Cell], width: usize, height: usize, char_width: usize) {
+        let bitmap = self
+            .bitmaps
+            .iter()
+            .find(|bitmap| font::fits(bitmap, width, height, char_width));
+        if let Some(bitmap) = bitmap {
+            font::draw_centered(bitmap, cells, width, char_width, self.color);
+        }
+    }
+}
+
+/// Hours, minutes and seconds of `time` in the local time zone.
+#[cfg(unix)]
+fn local_time(time: SystemTime) -> (u32, u32, u32) {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs()) as libc::time_t;
+    // SAFETY: `tm` is plain old data that `localtime_r` fills in, and both pointers are valid for
+    // the length of the call. Unlike `localtime` it doesn't share a buffer between threads.
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&seconds, &mut tm) }.is_null() {
+        return utc_time(time);
+    }
+    (tm.tm_hour as u32, tm.tm_min as u32, tm.tm_sec as u32)
+}
+
+/// Hours, minutes and seconds of `time` in the local time zone.
+#[cfg(windows)]
+fn local_time(time: SystemTime) -> (u32, u32, u32) {
+    use windows_sys::Win32::{
+        Foundation::{FILETIME, SYSTEMTIME},
+        System::Time::{FileTimeToSystemTime, SystemTimeToTzSpecificLocalTime},
+    };
+    /// Seconds from 1601, where Windows counts time from, to 1970
+    const UNIX_EPOCH_SECONDS: u64 = 11_644_473_600;
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    // Windows counts time in steps of 100 nanoseconds.
+    let ticks = (seconds + UNIX_EPOCH_SECONDS) * 10_000_000;
+    let file_time = FILETIME {
+        dwLowDateTime: ticks as u32,
+        dwHighDateTime: (ticks >> 32) as u32,
+    };
+    // SAFETY: `SYSTEMTIME` is plain old data the calls fill in, and all pointers are valid for the
+    // length of the calls. A null time zone stands for the one currently set.
+    let mut utc: SYSTEMTIME = unsafe { std::mem::zeroed() };
+    let mut local: SYSTEMTIME = unsafe { std::mem::zeroed() };
+    if unsafe { FileTimeToSystemTime(&file_time, &mut utc) } == 0
+        || unsafe { SystemTimeToTzSpecificLocalTime(std::ptr::null(), &utc, &mut local) } == 0
+    {
+        return utc_time(time);
+    }
+    (
+        local.wHour as u32,
+        local.wMinute as u32,
+        local.wSecond as u32,
+    )
+}
+
+/// There is no time zone database to go by here, so the clock shows UTC.
+#[cfg(not(any(unix, windows)))]
+fn local_time(time: SystemTime) -> (u32, u32, u32) {
+    utc_time(time)
+}
+
+fn utc_time(time: SystemTime) -> (u32, u32, u32) {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let of_day = (seconds % 86_400) as u32;
+    (of_day / 3600, of_day / 60 % 60, of_day % 60)
+}